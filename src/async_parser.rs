@@ -0,0 +1,203 @@
+//! Async chunk parser pattern.
+//!
+//! Async analogues of the parser traits for readers implementing
+//! `tokio::io::AsyncRead` and `AsyncSeek`. Parser structs are shared with the
+//! blocking API, only the reading, seeking and looping traits differ.
+//!
+//! Every returned future is `Send`, so parsers can run on a multi-threaded
+//! runtime. This requires `Send` parsers and readers, and headers that are
+//! `Send + Sync`.
+
+use std::future::Future;
+use std::io::SeekFrom;
+use std::mem::MaybeUninit;
+use std::pin::Pin;
+
+use num::traits::PrimInt;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt};
+
+use super::{Error, Result, Endian, Pod, ParserReader, ParserDepth};
+
+//------------------------------------------------------------------------------
+
+/// The `AsyncParserSeek` trait implements async positional API.
+pub trait AsyncParserSeek<R: AsyncSeek + Unpin + Send>: ParserReader<R> + Send {
+    /// Seek to a position in the reader.
+    #[inline] fn seek(&mut self, offset: u64) -> impl Future<Output = Result<u64>> + Send
+        { async move { Ok( self.reader().seek(SeekFrom::Start(offset)).await? ) } }
+
+    /// Skip a number of bytes, returning the number of bytes skipped.
+    #[inline] fn skip(&mut self, offset: u64) -> impl Future<Output = Result<u64>> + Send {
+        async move {
+            self.skip_to_pos(offset).await?;
+            Ok( offset )
+        }
+    }
+
+    /// Skip a number of bytes, returning the new reader position.
+    #[inline] fn skip_to_pos(&mut self, offset: u64) -> impl Future<Output = Result<u64>> + Send {
+        async move {
+            let delta = i64::try_from(offset).map_err(|_| Error::SizeOverflow)?;
+            Ok( self.reader().seek(SeekFrom::Current(delta)).await? )
+        }
+    }
+
+    /// Seek by a signed number of bytes, returning the new reader position.
+    #[inline] fn seek_relative(&mut self, delta: i64) -> impl Future<Output = Result<u64>> + Send
+        { async move { Ok( self.reader().seek(SeekFrom::Current(delta)).await? ) } }
+
+    /// Rewind a number of bytes.
+    #[inline] fn rewind(&mut self, offset: u64) -> impl Future<Output = Result<u64>> + Send {
+        async move {
+            let pos = self.position().await?.checked_sub(offset).ok_or(Error::SeekBeforeStart)?;
+            self.seek(pos).await
+        }
+    }
+
+    /// Get the current reader position.
+    #[inline] fn position(&mut self) -> impl Future<Output = Result<u64>> + Send
+        { async move { Ok( self.reader().stream_position().await? ) } }
+}
+
+/// The `AsyncParserRead` trait provides async typed read API.
+pub trait AsyncParserRead<R: AsyncRead + Unpin + Send>: ParserReader<R> + Send {
+    /// Read a plain old data type from the reader.
+    ///
+    /// The bytes are read straight into a zeroed `MaybeUninit<T>`, async
+    /// readers only ever see initialised memory.
+    fn read<T: Pod + Send>(&mut self) -> impl Future<Output = Result<T>> + Send {
+        async move {
+            let mut zeroed = MaybeUninit::<T>::zeroed(); // allocate zeroed memory
+            let buf = unsafe { std::slice::from_raw_parts_mut(zeroed.as_mut_ptr() as *mut u8, std::mem::size_of::<T>()) };
+            self.reader().read_exact(buf).await?;
+            Ok( unsafe { zeroed.assume_init() } ) // every byte was overwritten
+        }
+    }
+
+    /// Big endian read for all primitive integer types.
+    #[inline] fn read_be<T: PrimInt + Pod + Send>(&mut self) -> impl Future<Output = Result<T>> + Send
+        { async move { Ok( T::from_be(self.read().await?) ) } }
+
+    /// Little endian read for all primitive integer types.
+    #[inline] fn read_le<T: PrimInt + Pod + Send>(&mut self) -> impl Future<Output = Result<T>> + Send
+        { async move { Ok( T::from_le(self.read().await?) ) } }
+
+    /// Runtime endian read for all primitive integer types.
+    #[inline] fn read_int<T: PrimInt + Pod + Send>(&mut self, endian: Endian) -> impl Future<Output = Result<T>> + Send {
+        async move {
+            match endian {
+                Endian::Little => self.read_le().await,
+                Endian::Big => self.read_be().await
+            }
+        }
+    }
+}
+
+//------------------------------------------------------------------------------
+
+/// The `AsyncHeaderParser` trait defines unique async header parsing logic.
+pub trait AsyncHeaderParser<H> {
+    fn header(&mut self) -> impl Future<Output = Result<H>> + Send;
+}
+
+/// Signature for async parser closures.
+///
+/// Closures return a boxed future, e.g. `|parser, header| Box::pin(async move { .. })`.
+pub type AsyncParserFn<P,H> = for<'a> fn(parser: &'a mut P, header: &'a H) -> Pin<Box<dyn Future<Output = Result<u64>> + Send + 'a>>;
+
+/// The `AsyncChunkParser` trait defines the inner async parser loop.
+pub trait AsyncChunkParser<R: AsyncRead + AsyncSeek + Unpin + Send>: AsyncParserRead<R> + AsyncParserSeek<R> + ParserDepth {
+    /// Internal parser loop.
+    fn parse_loop<H: Send + Sync>(&mut self, f: AsyncParserFn<Self,H>, total_size: u64) -> impl Future<Output = Result<()>> + Send where Self: AsyncHeaderParser<H> {
+        async move {
+            loop {
+                let offset = self.position().await?;
+                let pos = async {
+                    let header = self.header().await?;
+                    let start = self.position().await?;
+                    let size = f(self, &header).await?; // the parser function is responsible for parsing the size
+                    let end = start.checked_add(size).ok_or(Error::SizeOverflow)?;
+                    let pos = self.position().await?;
+                    if pos != total_size && pos != end { return Err(Error::ChunkSizeMismatch { expected_end: end, actual_pos: pos }) } // function made a mistake
+                    Ok( pos )
+                }.await.map_err(|e| e.in_chunk(None, offset))?;
+                if pos == total_size { break Ok(()) } // function consumed chunk
+                else if pos <= offset { break Err(Error::ParseError.in_chunk(None, offset)) } // no progress
+            }
+        }
+    }
+
+    /// Parse top level chunk(s) from the reader.
+    #[inline]
+    fn parse<H: Send + Sync>(&mut self, f: AsyncParserFn<Self,H>) -> impl Future<Output = Result<()>> + Send where Self: AsyncHeaderParser<H> {
+        async move {
+            let total_size = self.reader().seek(SeekFrom::End(0)).await?;
+            self.reader().seek(SeekFrom::Start(0)).await?;
+            self.parse_loop(f, total_size).await
+        }
+    }
+
+    /// Parse nested subchunks within the main parse routine.
+    #[inline]
+    fn subchunks<H: Send + Sync>(&mut self, f: AsyncParserFn<Self,H>, total_size: u64) -> impl Future<Output = Result<()>> + Send where Self: AsyncHeaderParser<H> {
+        async move {
+            self.try_push()?;
+            let res = async {
+                let pos = self.position().await?;
+                self.parse_loop(f, pos.checked_add(total_size).ok_or(Error::SizeOverflow)?).await
+            }.await;
+            self.pop();
+            res
+        }
+    }
+}
+
+//------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TypeId;
+
+    // minimal async iff parser definition
+    struct IFFParser<R> { reader: R, depth: u8 }
+    impl<R> ParserReader<R> for IFFParser<R> { fn reader(&mut self) -> &mut R { &mut self.reader } }
+    impl<R: AsyncSeek + Unpin + Send> AsyncParserSeek<R> for IFFParser<R> {}
+    impl<R: AsyncRead + Unpin + Send> AsyncParserRead<R> for IFFParser<R> {}
+    impl<R> ParserDepth for IFFParser<R> { fn inner_depth(&mut self) -> &mut u8 { &mut self.depth } }
+    impl<R: AsyncRead + AsyncSeek + Unpin + Send> AsyncChunkParser<R> for IFFParser<R> {}
+
+    struct IFFHeader { typeid: TypeId, length: u32 }
+    impl<R: AsyncRead + Unpin + Send> AsyncHeaderParser<IFFHeader> for IFFParser<R> {
+        async fn header(&mut self) -> Result<IFFHeader>
+            { Ok( IFFHeader { typeid: self.read().await?, length: self.read_be().await? } ) }
+    }
+
+    // nonsense data to test basic functionality
+    const DATA: &[u8;24] = &[
+        0x46, 0x4f, 0x52, 0x4d, // "FORM" chunk typeid
+        0x00, 0x00, 0x00, 0x10, // Chunk size (16 bytes)
+        0x54, 0x45, 0x53, 0x54, // Subchunk typeid ("TEST")
+        0x54, 0x45, 0x53, 0x54, // "TEST" chunk typid
+        0x00, 0x00, 0x00, 0x04, // Chunk size (4 bytes)
+        0x01, 0x02, 0x03, 0x04, // Test data
+    ];
+
+    #[test]
+    fn parse() -> Result<()> {
+        fn send<F: Future + Send>(future: F) -> F { future } // futures may cross threads
+        let runtime = tokio::runtime::Builder::new_current_thread().build()?;
+        runtime.block_on(send(async {
+            let mut iff = IFFParser { reader: std::io::Cursor::new(DATA), depth: 0 };
+            iff.parse(|parser, header| Box::pin(async move {
+                assert!(header.typeid == *b"FORM");
+                parser.skip(4).await?;
+                parser.subchunks(|parser, header| Box::pin(async move {
+                    assert_eq!(parser.read_be::<u32>().await?, 0x01020304);
+                    Ok(header.length as u64)
+                }), 12).await?;
+                Ok(header.length as u64)
+            })).await
+        }))
+    }
+}
@@ -0,0 +1,225 @@
+//! Standard IFF parser.
+//!
+//! A ready-made parser for EA IFF 85 files. Group chunks `FORM`, `LIST` and
+//! `CAT ` are recursed into automatically, sizes are big endian and chunk
+//! bodies are padded to an even length. Users only supply a handler for the
+//! leaf chunks they care about.
+
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+
+use super::io::{Read, Seek, SeekFrom, Cursor};
+
+use super::{
+    Error, Result, FourCC, Endian, StandardHeader, HeaderParser, ChunkHeader, ChunkParser,
+    ChunkContext, ParseStats, ParserReader, ParserRead, ParserSeek, ParserDepth, ParserStats, ParserAlign
+};
+
+//------------------------------------------------------------------------------
+
+/// Signature for IFF leaf chunk handlers.
+///
+/// The handler receives the type of the enclosing group chunk, or `None` for
+/// top level chunks. Any part of the body left unread is skipped afterwards.
+pub type IffFn<R> = fn(parser: &mut IffParser<R>, form: Option<FourCC>, header: &StandardHeader) -> Result<()>;
+
+/// Check for the IFF group chunks `FORM`, `LIST` and `CAT `.
+#[inline] pub fn is_group(fourcc: FourCC) -> bool
+    { fourcc == *b"FORM" || fourcc == *b"LIST" || fourcc == *b"CAT " }
+
+/// Standard IFF parser.
+pub struct IffParser<R> {
+    reader: R,
+    depth: u8,
+    stats: ParseStats,
+    max_chunks: u64,
+    max_bytes: u64
+}
+
+impl<R> IffParser<R> {
+    /// Wrap a reader.
+    pub fn new(reader: R) -> IffParser<R>
+        { IffParser { reader, depth: 0, stats: ParseStats::default(), max_chunks: u64::MAX, max_bytes: u64::MAX } }
+
+    /// Limit the chunks parsed and bytes covered, see `ParserStats`.
+    pub fn with_budget(self, max_chunks: u64, max_bytes: u64) -> Self { IffParser { max_chunks, max_bytes, ..self } }
+
+    /// Reclaim the inner reader.
+    pub fn into_inner(self) -> R { self.reader }
+}
+
+impl<T: AsRef<[u8]>> IffParser<Cursor<T>> {
+    /// Parse an in-memory buffer.
+    pub fn cursor(data: T) -> Self { IffParser::new(Cursor::new(data)) }
+}
+
+impl IffParser<Cursor<Vec<u8>>> {
+    /// Parse an owned buffer.
+    pub fn from_vec(data: Vec<u8>) -> Self { IffParser::cursor(data) }
+}
+
+impl<R> ParserReader<R> for IffParser<R> { fn reader(&mut self) -> &mut R { &mut self.reader } }
+impl<R: Read> ParserRead<R> for IffParser<R> {}
+impl<R: Seek> ParserSeek<R> for IffParser<R> {}
+impl<R> ParserDepth for IffParser<R> { fn inner_depth(&mut self) -> &mut u8 { &mut self.depth } }
+impl<R> ParserStats for IffParser<R> {
+    fn stats(&self) -> &ParseStats { &self.stats }
+    fn stats_mut(&mut self) -> &mut ParseStats { &mut self.stats }
+    fn max_chunks(&self) -> u64 { self.max_chunks }
+    fn max_bytes(&self) -> u64 { self.max_bytes }
+}
+impl<R: Seek> ParserAlign<R> for IffParser<R> { fn alignment(&self) -> u64 { 2 } }
+impl<R: Read + Seek> ChunkParser<R> for IffParser<R> {
+    fn chunk_alignment(&self) -> u64 { 2 }
+    fn charge(&mut self, context: &ChunkContext, bytes: u64) -> Result<()> { self.charge_chunk(context, bytes) }
+}
+
+impl<R: Read> HeaderParser<StandardHeader> for IffParser<R> {
+    fn header(&mut self) -> Result<StandardHeader>
+        { self.read_standard_header(Endian::Big) }
+
+    fn as_chunk_header<'a>(&self, header: &'a StandardHeader) -> Option<&'a dyn ChunkHeader> { Some(header) }
+}
+
+impl<R: Read + Seek> IffParser<R> {
+    /// Parse the whole reader, calling `f` for every leaf chunk.
+    pub fn parse_iff(&mut self, f: IffFn<R>) -> Result<()> {
+        let total_size = self.reader.seek(SeekFrom::End(0))?;
+        self.reader.seek(SeekFrom::Start(0))?;
+        parse_group(self, f, is_group, None, total_size)
+    }
+}
+
+// Parse the chunks between the reader position and `end`, recursing into
+// group chunks. Shared with the RIFF parser.
+pub(crate) fn parse_group<P, R>(parser: &mut P, f: fn(&mut P, Option<FourCC>, &StandardHeader) -> Result<()>, group: fn(FourCC) -> bool, form: Option<FourCC>, end: u64) -> Result<()>
+where R: Read + Seek, P: HeaderParser<StandardHeader> + ChunkParser<R> + ParserAlign<R> {
+    loop {
+        let offset = parser.position()?;
+        if offset >= end { break Ok(()) } // all chunks consumed
+        let mut fourcc = None;
+        (|| {
+            let header = parser.header()?;
+            parser.validate_header(&header)?;
+            fourcc = Some(header.fourcc);
+            let start = parser.position()?;
+            let body_end = start.checked_add(header.size()).ok_or(Error::SizeOverflow)?;
+            if body_end > end { return Err(Error::ParseError) } // chunk overruns its parent
+            let context = ChunkContext { offset, depth: parser.depth(), total_size: end };
+            parser.charge(&context, body_end - offset)?;
+            if group(header.fourcc) {
+                if header.size() < 4 { return Err(Error::ParseError) } // no room for the form type
+                let form = parser.read_fourcc()?;
+                parser.try_push()?;
+                match parse_group(parser, f, group, Some(form), body_end) {
+                    res => { parser.pop(); res? }
+                }
+            } else {
+                f(parser, form, &header)?;
+                if parser.position()? > body_end { return Err(Error::ChunkOverrun) } // handler read too much
+            }
+            let padded = start.checked_add(header.size().next_multiple_of(parser.alignment())).ok_or(Error::SizeOverflow)?;
+            parser.seek(padded.min(end)) // skip the remainder and pad byte
+        })().map_err(|e| e.in_chunk(fourcc, offset))?;
+    }
+}
+
+//------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // FORM with an odd sized chunk and a nested LIST
+    const DATA: &[u8] = &[
+        0x46, 0x4f, 0x52, 0x4d, // "FORM" group chunk
+        0x00, 0x00, 0x00, 0x24, // Chunk size (36 bytes)
+        0x54, 0x45, 0x53, 0x54, // Form type ("TEST")
+        0x4f, 0x44, 0x44, 0x20, // "ODD " chunk
+        0x00, 0x00, 0x00, 0x03, // Chunk size (3 bytes)
+        0x01, 0x02, 0x03, 0x00, // Test data and pad byte
+        0x4c, 0x49, 0x53, 0x54, // "LIST" group chunk
+        0x00, 0x00, 0x00, 0x0c, // Chunk size (12 bytes)
+        0x49, 0x4e, 0x46, 0x4f, // List type ("INFO")
+        0x45, 0x56, 0x45, 0x4e, // "EVEN" chunk
+        0x00, 0x00, 0x00, 0x00, // Chunk size (0 bytes)
+    ];
+
+    #[test]
+    fn parse_iff() -> Result<()> {
+        IffParser::cursor(DATA).parse_iff(|parser, form, header| {
+            let form = form.expect("leaf inside a group");
+            if header.fourcc == *b"ODD " {
+                assert!(form == *b"TEST");
+                assert_eq!(parser.depth(), 1);
+                assert_eq!(parser.read::<u8>()?, 0x01); // remainder is skipped
+            } else {
+                assert!(header.fourcc == *b"EVEN" && form == *b"INFO");
+                assert_eq!(parser.depth(), 2);
+            }
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn parse_padded_chunks() -> Result<()> {
+        IffParser::cursor(DATA).parse(|parser, header: &StandardHeader| {
+            parser.skip(4)?; // form type
+            parser.subchunks(|parser, header: &StandardHeader| parser.skip(header.size()), header.size() - 4)?;
+            Ok(header.size())
+        })
+    }
+
+    #[test]
+    fn from_vec() -> Result<()> {
+        let mut iff = IffParser::from_vec(DATA.to_vec());
+        assert_eq!(iff.chunks::<StandardHeader>().count(), 1);
+        assert_eq!(iff.into_inner().into_inner(), DATA);
+        Ok(())
+    }
+
+    #[test]
+    fn odd_top_level_chunks() -> Result<()> {
+        let data = &DATA[12..]; // "ODD " with its pad byte, then "LIST"
+        let fourccs = IffParser::cursor(data).chunks::<StandardHeader>()
+            .map(|header| header.map(|header| header.fourcc)).collect::<Result<Vec<_>>>()?;
+        assert!(fourccs.len() == 2 && fourccs[0] == *b"ODD " && fourccs[1] == *b"LIST");
+        let header: Option<StandardHeader> = IffParser::cursor(data).find(b"LIST")?;
+        assert!(header.is_some_and(|header| header.size == 12));
+        let data = [&DATA[24..], &DATA[12..23]].concat(); // final odd chunk without its pad byte
+        assert_eq!(IffParser::cursor(&data[..]).chunks::<StandardHeader>().count(), 2);
+        assert_eq!(IffParser::cursor(&data[..]).find_all::<StandardHeader>(b"ODD ")?.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn parse_iff_short_group() -> Result<()> {
+        let mut data = DATA.to_vec();
+        data[7] = 0x02; // FORM too small for its form type
+        let err = IffParser::cursor(&data[..]).parse_iff(|_, _, _| Ok(())).unwrap_err();
+        assert!(matches!(err, Error::Chunk { offset: 0, .. }));
+        assert!(matches!(err.kind(), Error::ParseError));
+        Ok(())
+    }
+
+    #[test]
+    fn parse_iff_budget() -> Result<()> {
+        let err = IffParser::cursor(DATA).with_budget(3, u64::MAX).parse_iff(|_, _, _| Ok(())).unwrap_err();
+        assert!(matches!(err, Error::Chunk { offset: 36, .. }));
+        assert!(matches!(err.kind(), Error::BudgetExceeded));
+        let err = IffParser::cursor(DATA).with_budget(u64::MAX, 82).parse_iff(|_, _, _| Ok(())).unwrap_err();
+        assert!(matches!(err, Error::Chunk { offset: 36, .. })); // nested bytes count once per group
+        let mut iff = IffParser::cursor(DATA).with_budget(4, 83);
+        iff.parse_iff(|_, _, _| Ok(()))?;
+        assert_eq!(*iff.stats(), ParseStats { chunk_count: 4, max_depth: 2, bytes_parsed: 44, bytes_visited: 83 });
+        Ok(())
+    }
+
+    #[test]
+    fn parse_iff_overrun() -> Result<()> {
+        let err = IffParser::cursor(DATA).parse_iff(|parser, _, _| { parser.skip(4)?; Ok(()) }).unwrap_err();
+        assert!(matches!(err, Error::Chunk { offset: 12, .. }));
+        assert!(matches!(err.kind(), Error::ChunkOverrun));
+        Ok(())
+    }
+}
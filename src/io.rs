@@ -0,0 +1,274 @@
+//! Reader and writer traits used by the parsers.
+//!
+//! With the default `std` feature these are the `std::io` items themselves.
+//! Without it, a minimal in-crate subset takes their place, so in-memory
+//! buffers can be parsed in `no_std` builds with `alloc`. Only the methods
+//! the parser traits rely on are provided.
+
+#[cfg(feature = "std")]
+pub use std::io::{Read, Write, Seek, SeekFrom, Cursor, Take, Error, ErrorKind, Result};
+
+#[cfg(not(feature = "std"))]
+pub use self::core_io::*;
+
+#[cfg(not(feature = "std"))]
+mod core_io {
+    use alloc::vec::Vec;
+
+    //--------------------------------------------------------------------------
+
+    /// Category of an I/O error.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[non_exhaustive]
+    pub enum ErrorKind {
+        UnexpectedEof, // Reader ended before the read completed.
+        WriteZero, // Writer accepted no more bytes.
+        InvalidInput, // Seek to a negative or overflowing position.
+        Unsupported, // Operation not supported by the reader.
+        Other // Any other error.
+    }
+
+    /// I/O error with a static description.
+    #[derive(Debug)]
+    pub struct Error {
+        kind: ErrorKind,
+        message: &'static str
+    }
+
+    impl Error {
+        /// Create an error of the given kind.
+        pub fn new(kind: ErrorKind, message: &'static str) -> Error { Error { kind, message } }
+
+        /// Create an error of kind `Other`.
+        pub fn other(message: &'static str) -> Error { Error::new(ErrorKind::Other, message) }
+
+        /// Get the error kind.
+        pub fn kind(&self) -> ErrorKind { self.kind }
+    }
+
+    impl From<ErrorKind> for Error {
+        fn from(kind: ErrorKind) -> Self { Error::new(kind, "i/o error") }
+    }
+
+    impl core::fmt::Display for Error {
+        fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result { f.write_str(self.message) }
+    }
+
+    impl core::error::Error for Error {}
+
+    /// I/O result type.
+    pub type Result<T> = core::result::Result<T, Error>;
+
+    //--------------------------------------------------------------------------
+
+    /// Seek origin, see `std::io::SeekFrom`.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum SeekFrom {
+        Start(u64),
+        End(i64),
+        Current(i64)
+    }
+
+    /// Byte source, see `std::io::Read`.
+    pub trait Read {
+        /// Read some bytes into `buf`, returning how many were read.
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize>;
+
+        /// Fill `buf` completely.
+        fn read_exact(&mut self, mut buf: &mut [u8]) -> Result<()> {
+            while !buf.is_empty() {
+                match self.read(buf)? {
+                    0 => return Err(ErrorKind::UnexpectedEof.into()),
+                    n => buf = &mut buf[n..]
+                }
+            }
+            Ok(())
+        }
+
+        /// Append every remaining byte to `buf`, returning how many were read.
+        fn read_to_end(&mut self, buf: &mut Vec<u8>) -> Result<usize> {
+            let start = buf.len();
+            let mut chunk = [0; 256];
+            loop {
+                match self.read(&mut chunk)? {
+                    0 => break Ok( buf.len() - start ),
+                    n => buf.extend_from_slice(&chunk[..n])
+                }
+            }
+        }
+
+        /// Borrow the reader.
+        fn by_ref(&mut self) -> &mut Self where Self: Sized { self }
+
+        /// Limit the reader to `limit` bytes.
+        fn take(self, limit: u64) -> Take<Self> where Self: Sized { Take { inner: self, limit } }
+    }
+
+    /// Byte sink, see `std::io::Write`.
+    pub trait Write {
+        /// Write some bytes from `buf`, returning how many were written.
+        fn write(&mut self, buf: &[u8]) -> Result<usize>;
+
+        /// Flush buffered bytes.
+        fn flush(&mut self) -> Result<()>;
+
+        /// Write all of `buf`.
+        fn write_all(&mut self, mut buf: &[u8]) -> Result<()> {
+            while !buf.is_empty() {
+                match self.write(buf)? {
+                    0 => return Err(ErrorKind::WriteZero.into()),
+                    n => buf = &buf[n..]
+                }
+            }
+            Ok(())
+        }
+    }
+
+    /// Positioned stream, see `std::io::Seek`.
+    pub trait Seek {
+        /// Seek to a position, returning the new position from the start.
+        fn seek(&mut self, pos: SeekFrom) -> Result<u64>;
+
+        /// Get the current position.
+        fn stream_position(&mut self) -> Result<u64> { self.seek(SeekFrom::Current(0)) }
+    }
+
+    impl<R: Read + ?Sized> Read for &mut R {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize> { (**self).read(buf) }
+    }
+
+    impl<W: Write + ?Sized> Write for &mut W {
+        fn write(&mut self, buf: &[u8]) -> Result<usize> { (**self).write(buf) }
+        fn flush(&mut self) -> Result<()> { (**self).flush() }
+    }
+
+    impl<S: Seek + ?Sized> Seek for &mut S {
+        fn seek(&mut self, pos: SeekFrom) -> Result<u64> { (**self).seek(pos) }
+    }
+
+    impl Read for &[u8] {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+            let len = buf.len().min(self.len());
+            let (head, tail) = self.split_at(len);
+            buf[..len].copy_from_slice(head);
+            *self = tail;
+            Ok( len )
+        }
+    }
+
+    impl Write for Vec<u8> {
+        fn write(&mut self, buf: &[u8]) -> Result<usize> {
+            self.extend_from_slice(buf);
+            Ok( buf.len() )
+        }
+        fn flush(&mut self) -> Result<()> { Ok(()) }
+    }
+
+    //--------------------------------------------------------------------------
+
+    /// Reader limited to a number of bytes, see `Read::take`.
+    pub struct Take<R> {
+        inner: R,
+        limit: u64
+    }
+
+    impl<R> Take<R> {
+        /// Get the number of bytes left before end of file is reported.
+        pub fn limit(&self) -> u64 { self.limit }
+
+        /// Reclaim the inner reader.
+        pub fn into_inner(self) -> R { self.inner }
+    }
+
+    impl<R: Read> Read for Take<R> {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+            let max = usize::try_from(self.limit).map_or(buf.len(), |limit| limit.min(buf.len()));
+            let len = self.inner.read(&mut buf[..max])?;
+            self.limit -= len as u64;
+            Ok( len )
+        }
+    }
+
+    /// In-memory reader and writer, see `std::io::Cursor`.
+    #[derive(Debug, Clone, Default)]
+    pub struct Cursor<T> {
+        inner: T,
+        position: u64
+    }
+
+    impl<T> Cursor<T> {
+        /// Wrap a buffer, starting at position zero.
+        pub fn new(inner: T) -> Cursor<T> { Cursor { inner, position: 0 } }
+
+        /// Reclaim the inner buffer.
+        pub fn into_inner(self) -> T { self.inner }
+
+        /// Borrow the inner buffer.
+        pub fn get_ref(&self) -> &T { &self.inner }
+
+        /// Mutably borrow the inner buffer.
+        pub fn get_mut(&mut self) -> &mut T { &mut self.inner }
+
+        /// Get the cursor position.
+        pub fn position(&self) -> u64 { self.position }
+
+        /// Set the cursor position, which may lie past the end of the buffer.
+        pub fn set_position(&mut self, position: u64) { self.position = position }
+    }
+
+    impl<T: AsRef<[u8]>> Cursor<T> {
+        // Bytes from the cursor position to the end of the buffer.
+        fn remaining(&self) -> &[u8] {
+            let data = self.inner.as_ref();
+            let start = usize::try_from(self.position).map_or(data.len(), |pos| pos.min(data.len()));
+            &data[start..]
+        }
+    }
+
+    impl<T: AsRef<[u8]>> Read for Cursor<T> {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+            let len = self.remaining().read(buf)?;
+            self.position += len as u64;
+            Ok( len )
+        }
+    }
+
+    impl<T: AsRef<[u8]>> Seek for Cursor<T> {
+        fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+            let (base, delta) = match pos {
+                SeekFrom::Start(offset) => { self.position = offset; return Ok( offset ) },
+                SeekFrom::End(delta) => (self.inner.as_ref().len() as u64, delta),
+                SeekFrom::Current(delta) => (self.position, delta)
+            };
+            self.position = base.checked_add_signed(delta)
+                .ok_or(Error::new(ErrorKind::InvalidInput, "invalid seek to a negative or overflowing position"))?;
+            Ok( self.position )
+        }
+    }
+
+    // Overwrite bytes at the cursor position, growing the vector as needed.
+    impl Write for Cursor<Vec<u8>> {
+        fn write(&mut self, buf: &[u8]) -> Result<usize> {
+            let start = usize::try_from(self.position).map_err(|_| Error::from(ErrorKind::InvalidInput))?;
+            let end = start.checked_add(buf.len()).ok_or(Error::from(ErrorKind::InvalidInput))?;
+            if self.inner.len() < end { self.inner.resize(end, 0) }
+            self.inner[start..end].copy_from_slice(buf);
+            self.position = end as u64;
+            Ok( buf.len() )
+        }
+        fn flush(&mut self) -> Result<()> { Ok(()) }
+    }
+
+    // Overwrite bytes at the cursor position, stopping at the end of the slice.
+    impl Write for Cursor<&mut [u8]> {
+        fn write(&mut self, buf: &[u8]) -> Result<usize> {
+            let data = &mut self.inner[..];
+            let start = usize::try_from(self.position).map_or(data.len(), |pos| pos.min(data.len()));
+            let len = buf.len().min(data.len() - start);
+            data[start..start + len].copy_from_slice(&buf[..len]);
+            self.position += len as u64;
+            Ok( len )
+        }
+        fn flush(&mut self) -> Result<()> { Ok(()) }
+    }
+}
@@ -0,0 +1,206 @@
+//! ISO base media file parser.
+//!
+//! A ready-made parser for MP4, MOV and other ISO/IEC 14496-12 files. Atoms
+//! start with a big endian size that includes the header, followed by the four
+//! character code. A size of 1 means a 64-bit size follows the code, and a size
+//! of 0 means the atom extends to the end of the file. Container atoms are
+//! recursed into automatically, users only supply a handler for the leaf atoms
+//! they care about.
+
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+
+use super::io::{Read, Seek, SeekFrom, Cursor};
+
+use super::{
+    Error, Result, FourCC, SizeConvention, HeaderParser, ChunkHeader, ChunkParser,
+    ChunkContext, ParseStats, ParserReader, ParserRead, ParserSeek, ParserDepth, ParserStats
+};
+
+//------------------------------------------------------------------------------
+
+/// Signature for ISO base media leaf atom handlers.
+///
+/// The handler receives the type of the enclosing container atom, or `None`
+/// for top level atoms. Any part of the body left unread is skipped afterwards.
+pub type IsoFn<R> = fn(parser: &mut IsoParser<R>, parent: Option<FourCC>, header: &AtomHeader) -> Result<()>;
+
+/// Check for the container atoms holding nothing but child atoms.
+#[inline] pub fn is_container(fourcc: FourCC) -> bool {
+    [b"moov", b"trak", b"mdia", b"minf", b"stbl", b"dinf", b"edts", b"udta", b"mvex", b"moof", b"traf", b"mfra"]
+        .iter().any(|code| fourcc == **code)
+}
+
+/// Atom header with the size converted to the body size.
+#[derive(Debug, Clone, Copy)]
+pub struct AtomHeader {
+    pub fourcc: FourCC, // Atom type.
+    pub size: u64, // Atom body size, excluding the header.
+    pub header_size: u64 // Header size, 16 bytes for 64-bit sizes, otherwise 8.
+}
+
+crate::impl_chunk_header!(AtomHeader, fourcc: fourcc, size: size);
+
+/// Standard ISO base media parser.
+pub struct IsoParser<R> {
+    reader: R,
+    depth: u8,
+    stats: ParseStats,
+    max_chunks: u64,
+    max_bytes: u64
+}
+
+impl<R> IsoParser<R> {
+    /// Wrap a reader.
+    pub fn new(reader: R) -> IsoParser<R>
+        { IsoParser { reader, depth: 0, stats: ParseStats::default(), max_chunks: u64::MAX, max_bytes: u64::MAX } }
+
+    /// Limit the chunks parsed and bytes covered, see `ParserStats`.
+    pub fn with_budget(self, max_chunks: u64, max_bytes: u64) -> Self { IsoParser { max_chunks, max_bytes, ..self } }
+
+    /// Reclaim the inner reader.
+    pub fn into_inner(self) -> R { self.reader }
+}
+
+impl<T: AsRef<[u8]>> IsoParser<Cursor<T>> {
+    /// Parse an in-memory buffer.
+    pub fn cursor(data: T) -> Self { IsoParser::new(Cursor::new(data)) }
+}
+
+impl IsoParser<Cursor<Vec<u8>>> {
+    /// Parse an owned buffer.
+    pub fn from_vec(data: Vec<u8>) -> Self { IsoParser::cursor(data) }
+}
+
+impl<R> ParserReader<R> for IsoParser<R> { fn reader(&mut self) -> &mut R { &mut self.reader } }
+impl<R: Read> ParserRead<R> for IsoParser<R> {}
+impl<R: Seek> ParserSeek<R> for IsoParser<R> {}
+impl<R> ParserDepth for IsoParser<R> { fn inner_depth(&mut self) -> &mut u8 { &mut self.depth } }
+impl<R> ParserStats for IsoParser<R> {
+    fn stats(&self) -> &ParseStats { &self.stats }
+    fn stats_mut(&mut self) -> &mut ParseStats { &mut self.stats }
+    fn max_chunks(&self) -> u64 { self.max_chunks }
+    fn max_bytes(&self) -> u64 { self.max_bytes }
+}
+impl<R: Read + Seek> ChunkParser<R> for IsoParser<R> {
+    fn charge(&mut self, context: &ChunkContext, bytes: u64) -> Result<()> { self.charge_chunk(context, bytes) }
+}
+
+impl<R: Read + Seek> HeaderParser<AtomHeader> for IsoParser<R> {
+    fn header(&mut self) -> Result<AtomHeader> {
+        let size = self.read_be::<u32>()?;
+        let fourcc = self.read_fourcc()?;
+        let (declared, header_size) = match size {
+            0 => { // atom extends to the end of the file
+                let start = self.position()?;
+                let end = self.reader.seek(SeekFrom::End(0))?;
+                self.seek(start)?;
+                (end - start + 8, 8)
+            },
+            1 => (self.read_be::<u64>()?, 16), // 64-bit size follows the type
+            size => (size as u64, 8)
+        };
+        let size = SizeConvention::Chunk.body_size(declared, header_size)?;
+        Ok( AtomHeader { fourcc, size, header_size } )
+    }
+
+    fn as_chunk_header<'a>(&self, header: &'a AtomHeader) -> Option<&'a dyn ChunkHeader> { Some(header) }
+}
+
+impl<R: Read + Seek> IsoParser<R> {
+    /// Parse the whole reader, calling `f` for every leaf atom.
+    pub fn parse_isobmff(&mut self, f: IsoFn<R>) -> Result<()> {
+        let total_size = self.reader.seek(SeekFrom::End(0))?;
+        self.reader.seek(SeekFrom::Start(0))?;
+        self.parse_atoms(f, None, total_size)
+    }
+
+    // Parse the atoms between the reader position and `end`, recursing into
+    // container atoms.
+    fn parse_atoms(&mut self, f: IsoFn<R>, parent: Option<FourCC>, end: u64) -> Result<()> {
+        loop {
+            let offset = self.position()?;
+            if offset >= end { break Ok(()) } // all atoms consumed
+            let mut fourcc = None;
+            (|| {
+                let header = self.header()?;
+                self.validate_header(&header)?;
+                fourcc = Some(header.fourcc);
+                let start = self.position()?;
+                let body_end = start.checked_add(header.size).ok_or(Error::SizeOverflow)?;
+                if body_end > end { return Err(Error::ParseError) } // atom overruns its parent
+                let context = ChunkContext { offset, depth: self.depth(), total_size: end };
+                self.charge(&context, body_end - offset)?;
+                if is_container(header.fourcc) {
+                    self.try_push()?;
+                    match self.parse_atoms(f, Some(header.fourcc), body_end) {
+                        res => { self.pop(); res? }
+                    }
+                } else {
+                    f(self, parent, &header)?;
+                    if self.position()? > body_end { return Err(Error::ChunkOverrun) } // handler read too much
+                }
+                self.seek(body_end) // skip the remainder
+            })().map_err(|e| e.in_chunk(fourcc, offset))?;
+        }
+    }
+}
+
+//------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // ftyp atom, moov container with a 64-bit sized atom and an mdat to the end
+    const DATA: &[u8] = &[
+        0x00, 0x00, 0x00, 0x0c, // Atom size (12 bytes)
+        0x66, 0x74, 0x79, 0x70, // "ftyp" atom
+        0x69, 0x73, 0x6f, 0x6d, // Major brand ("isom")
+        0x00, 0x00, 0x00, 0x1c, // Atom size (28 bytes)
+        0x6d, 0x6f, 0x6f, 0x76, // "moov" container atom
+        0x00, 0x00, 0x00, 0x01, // Atom size (64-bit size follows)
+        0x6d, 0x76, 0x68, 0x64, // "mvhd" atom
+        0x00, 0x00, 0x00, 0x00, // 64-bit atom size
+        0x00, 0x00, 0x00, 0x14, // (20 bytes)
+        0x01, 0x02, 0x03, 0x04, // Test data
+        0x00, 0x00, 0x00, 0x00, // Atom size (to the end of the file)
+        0x6d, 0x64, 0x61, 0x74, // "mdat" atom
+        0x05, 0x06, 0x07,       // Test data
+    ];
+
+    #[test]
+    fn header() -> Result<()> {
+        let mut iso = IsoParser::cursor(DATA);
+        let header: AtomHeader = iso.header()?;
+        assert!(header.fourcc == *b"ftyp" && header.size == 4 && header.header_size == 8);
+        iso.seek(20)?;
+        let header: AtomHeader = iso.header()?;
+        assert!(header.fourcc == *b"mvhd" && header.size == 4 && header.header_size == 16);
+        iso.seek(40)?;
+        let header: AtomHeader = iso.header()?;
+        assert!(header.fourcc == *b"mdat" && header.size == 3);
+        assert_eq!(iso.position()?, 48);
+        let err = IsoParser::cursor(&[0x00, 0x00, 0x00, 0x04, 0x66, 0x72, 0x65, 0x65][..]).header().unwrap_err();
+        assert!(matches!(err, Error::ParseError)); // size smaller than the header
+        Ok(())
+    }
+
+    #[test]
+    fn parse_isobmff() -> Result<()> {
+        IsoParser::cursor(DATA).parse_isobmff(|parser, parent, header| {
+            if header.fourcc == *b"mvhd" {
+                assert!(parent.is_some_and(|parent| parent == *b"moov"));
+                assert_eq!(parser.depth(), 1);
+                assert_eq!(parser.read_be::<u16>()?, 0x0102); // remainder is skipped
+            } else {
+                assert!(parent.is_none());
+                assert_eq!(parser.depth(), 0);
+            }
+            Ok(())
+        })?;
+        let err = IsoParser::cursor(&DATA[..30]).parse_isobmff(|_, _, _| Ok(())).unwrap_err();
+        assert!(matches!(err, Error::Chunk { offset: 12, .. }));
+        Ok(())
+    }
+}
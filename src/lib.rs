@@ -1,31 +1,170 @@
 //! Generic chunk parser pattern.
+//!
+//! The default `std` feature adds file, stream and `std::io` support. Without
+//! it the crate builds for `no_std` targets with `alloc`, parsing in-memory
+//! buffers through the minimal traits in `io`. The `chunk_parser` attribute
+//! names `std::io` types, so `no_std` parsers implement the traits by hand or
+//! use the ready-made `iff`, `riff` and `isobmff` parsers.
 
-use std::io::{Read, Seek, SeekFrom, Error as IoError};
-use std::mem::MaybeUninit;
-use std::path::PathBuf;
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+#[cfg(not(feature = "std"))]
+use alloc::{boxed::Box, string::String, vec::Vec};
+use core::mem::MaybeUninit;
+use core::ops::ControlFlow;
+#[cfg(feature = "std")]
+use std::path::{Path, PathBuf};
+
+use io::{Read, Write, Seek, SeekFrom, Error as IoError};
 
 use num::traits::PrimInt;
 
 pub use fourcc::{FourCC, TypeId};
 pub use chunk_parser_derive::chunk_parser;
 
+// Enter a tracing span until the end of the enclosing block, compiled out
+// entirely without the `tracing` feature.
+macro_rules! trace_span {
+    ($name:literal, $($field:tt)*) => {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!($name, $($field)*).entered();
+    };
+}
+
+// Compile the README example as a doctest.
+#[cfg(doctest)]
+#[doc = include_str!("../README.md")]
+struct ReadmeDoctests;
+
+pub mod io;
+pub mod iff;
+pub mod isobmff;
+pub mod riff;
+
+#[cfg(feature = "async")]
+pub mod async_parser;
+
 //------------------------------------------------------------------------------
 
 /// Error type common to all chunk parsers.
 #[derive(Debug)]
 pub enum Error {
-    IoError(IoError), // Forwarded `std::io::Error`.
+    IoError(IoError), // Forwarded `io::Error`.
+    UnexpectedEof, // Reader ended before the read completed.
     ParseError, // General parser error.
     SizeOverflow, // Size type overflow error.
+    ChunkOverrun, // Read past the end of the current chunk.
+    InvalidUtf8, // String data is not valid UTF-8.
+    InvalidFourCC, // Four character code contains non printable bytes.
     Unimplemented, // Unimplemented code paths.
-    UnknownChunk // Unknown chunk type.
+    UnknownChunk { fourcc: FourCC, offset: u64 }, // Unknown chunk type, with the reader position where it was rejected.
+    DepthExceeded, // Nesting deeper than the maximum parser depth.
+    SeekBeforeStart, // Rewind past the start of the reader.
+    SizeMismatch { declared: u64, actual: u64 }, // Declared size does not match the data.
+    ChunkSizeMismatch { expected_end: u64, actual_pos: u64 }, // Parser function returned a size not matching what it read.
+    ChecksumMismatch { expected: u32, actual: u32 }, // Stored checksum does not match the data.
+    BudgetExceeded, // Chunk or byte budget exhausted.
+    Chunk { fourcc: Option<FourCC>, offset: u64, kind: Box<Error> } // Error raised within a chunk.
+}
+
+impl Error {
+    /// Attach chunk context to an error, keeping the innermost context.
+    pub fn in_chunk(self, fourcc: Option<FourCC>, offset: u64) -> Error {
+        match self {
+            Error::Chunk { .. } => self,
+            kind => {
+                #[cfg(feature = "tracing")]
+                tracing::error!(offset, fourcc = ?fourcc, error = %kind, "parse error");
+                Error::Chunk { fourcc, offset, kind: Box::new(kind) }
+            }
+        }
+    }
+
+    /// Get the underlying error without chunk context.
+    pub fn kind(&self) -> &Error {
+        match self {
+            Error::Chunk { kind, .. } => kind,
+            _ => self
+        }
+    }
+}
+
+// Wrap `io::Error` with `Error`, separating out truncation.
+impl From<IoError> for Error {
+    fn from(e: IoError) -> Self {
+        match e.kind() {
+            io::ErrorKind::UnexpectedEof => Error::UnexpectedEof,
+            _ => Error::IoError(e)
+        }
+    }
+}
+
+// Human readable error messages.
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Error::IoError(e) => write!(f, "io error: {}", e),
+            Error::UnexpectedEof => write!(f, "unexpected end of file"),
+            Error::ParseError => write!(f, "parse error"),
+            Error::SizeOverflow => write!(f, "size overflow"),
+            Error::ChunkOverrun => write!(f, "read past the end of the chunk"),
+            Error::InvalidUtf8 => write!(f, "invalid utf-8 string"),
+            Error::InvalidFourCC => write!(f, "invalid four character code"),
+            Error::Unimplemented => write!(f, "unimplemented"),
+            Error::UnknownChunk { fourcc, offset } => write!(f, "unknown chunk '{}' at offset {:#x}", fourcc, offset),
+            Error::DepthExceeded => write!(f, "maximum depth exceeded"),
+            Error::SeekBeforeStart => write!(f, "seek before the start of the reader"),
+            Error::BudgetExceeded => write!(f, "parse budget exceeded"),
+            Error::SizeMismatch { declared, actual } =>
+                write!(f, "size mismatch: declared {} bytes, found {}", declared, actual),
+            Error::ChunkSizeMismatch { expected_end, actual_pos } =>
+                write!(f, "chunk size mismatch: expected end {:#x}, found {:#x}", expected_end, actual_pos),
+            Error::ChecksumMismatch { expected, actual } =>
+                write!(f, "checksum mismatch: expected {:#010x}, got {:#010x}", expected, actual),
+            Error::Chunk { fourcc: Some(fourcc), offset, kind } =>
+                write!(f, "{} in '{}' at offset {:#x}", kind, fourcc, offset),
+            Error::Chunk { fourcc: None, offset, kind } =>
+                write!(f, "{} at offset {:#x}", kind, offset)
+        }
+    }
 }
 
-// Wrap `std::io::Error` with `Error`.
-impl From<IoError> for Error { fn from(e: IoError) -> Self { Error::IoError(e) } }
+// Forward the inner `io::Error` as the error source.
+impl core::error::Error for Error {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            Error::IoError(e) => Some(e),
+            Error::Chunk { kind, .. } => Some(kind.as_ref()),
+            _ => None
+        }
+    }
+}
 
 /// Error type is always an `Error` enum.
-pub type Result<T> = std::result::Result<T, Error>;
+pub type Result<T> = core::result::Result<T, Error>;
+
+/// Convert a header size field to a `u64` chunk size.
+///
+/// Negative or oversized values return `Error::SizeOverflow`.
+#[inline] pub fn to_size(n: impl TryInto<u64>) -> Result<u64>
+    { n.try_into().map_err(|_| Error::SizeOverflow) }
+
+/// Convert a chunk size to a buffer length.
+///
+/// Sizes beyond the address space of 32 bit targets return `Error::SizeOverflow`.
+#[inline] pub fn to_usize(n: u64) -> Result<usize>
+    { usize::try_from(n).map_err(|_| Error::SizeOverflow) }
+
+//------------------------------------------------------------------------------
+
+/// Byte order used for runtime endian reads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endian {
+    Little, // Least significant byte first.
+    Big // Most significant byte first.
+}
 
 //------------------------------------------------------------------------------
 
@@ -35,14 +174,48 @@ pub type Result<T> = std::result::Result<T, Error>;
 /// implementing a combination of `std::io::Read` and `Seek`. This generic trait
 /// adds mutable borrowed access and allows reclaiming the reader for any type
 /// `<R>` that implements `DummyReader<R>`.
+///
+/// Parsers do not implement `std::io::Read` themselves, as `Read::read` would
+/// make calls to `ParserRead::read` ambiguous. Pass `parser.reader()` to APIs
+/// taking `impl Read`, or use `ParserRead::copy_to` for a chunk body.
 pub trait ParserReader<R> {
     /// Access the inner reader.
     fn reader(&mut self) -> &mut R;
 
-    // Replace with a dummy file.
-    fn take_reader(&mut self) -> R where R: DummyReader {
-        std::mem::replace(self.reader(), R::dummy())
+    /// Reclaim the inner reader, leaving an empty dummy reader in its place.
+    ///
+    /// Fails without touching the inner reader if the dummy can't be created.
+    fn take_reader(&mut self) -> Result<R> where R: DummyReader {
+        let dummy = R::dummy()?;
+        Ok( core::mem::replace(self.reader(), dummy) )
     }
+
+    /// Borrow a view of the inner reader limited to `size` bytes.
+    #[inline] fn chunk_reader(&mut self, size: u64) -> io::Take<&mut R> where R: Read
+        { self.reader().take(size) }
+}
+
+/// The `ParserTake` trait reclaims a reader stored as `Option<R>`.
+///
+/// Keeping the reader in an `Option` lets any reader type be handed back
+/// without a `DummyReader` placeholder. Parsers implementing this trait are
+/// expected to panic in `ParserReader::reader` once the reader has been taken.
+pub trait ParserTake<R>: ParserReader<R> {
+    /// Access the inner optional reader.
+    fn inner_reader(&mut self) -> &mut Option<R>;
+
+    /// Reclaim the inner reader, or `None` if it was already taken.
+    #[inline] fn try_take_reader(&mut self) -> Option<R>
+        { self.inner_reader().take() }
+}
+
+/// Saved reader position, see `ParserSeek::bookmark`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bookmark(u64);
+
+impl Bookmark {
+    /// Get the saved position.
+    pub fn position(&self) -> u64 { self.0 }
 }
 
 /// The `ParserSeek` trait implements positional API.
@@ -59,21 +232,83 @@ pub trait ParserSeek<R: Seek>: ParserReader<R> {
     }
 
     /// Skip a number of bytes.
+    ///
+    /// Returns the number of bytes skipped, so parser closures can return
+    /// `parser.skip(header.size())` directly. See `skip_to_pos`.
     #[inline] fn skip(&mut self, offset: u64) -> Result<u64> {
-        let pos = SeekFrom::Current(offset as i64);
-        self.reader().seek(pos)?;
+        self.skip_to_pos(offset)?;
         Ok( offset )
     }
 
+    /// Skip a number of bytes, returning the new reader position.
+    #[inline] fn skip_to_pos(&mut self, offset: u64) -> Result<u64> {
+        let pos = SeekFrom::Current(i64::try_from(offset).map_err(|_| Error::SizeOverflow)?);
+        Ok( self.reader().seek(pos)? )
+    }
+
+    /// Seek by a signed number of bytes, returning the new reader position.
+    #[inline] fn seek_relative(&mut self, delta: i64) -> Result<u64>
+        { Ok( self.reader().seek(SeekFrom::Current(delta))? ) }
+
+    /// Skip at most `offset` bytes, stopping at the end of the reader.
+    ///
+    /// Returns the number of bytes actually skipped.
+    fn skip_clamped(&mut self, offset: u64) -> Result<u64> {
+        let pos = self.position()?;
+        let end = self.reader().seek(SeekFrom::End(0))?;
+        let target = pos.saturating_add(offset).min(end.max(pos));
+        self.seek(target)?;
+        Ok( target - pos )
+    }
+
+    /// Skip the remainder of a chunk body ending at `chunk_end`.
+    ///
+    /// Returns the number of bytes skipped. A reader already past `chunk_end`
+    /// returns `Error::ChunkOverrun`.
+    fn skip_to_end(&mut self, chunk_end: u64) -> Result<u64> {
+        let pos = self.position()?;
+        let remaining = chunk_end.checked_sub(pos).ok_or(Error::ChunkOverrun)?;
+        self.seek(chunk_end)?;
+        Ok( remaining )
+    }
+
     /// Rewind a number of bytes.
     #[inline] fn rewind(&mut self, offset: u64) -> Result<u64> {
-        let pos = SeekFrom::Current(-(offset as i64));
-        Ok( self.reader().seek(pos)? )
+        let pos = self.position()?.checked_sub(offset).ok_or(Error::SeekBeforeStart)?;
+        self.seek(pos)
     }
 
     /// Get the current reader position.
     #[inline] fn position(&mut self) -> Result<u64>
         { Ok( self.reader().stream_position()? ) }
+
+    /// Save the current reader position.
+    #[inline] fn bookmark(&mut self) -> Result<Bookmark>
+        { Ok( Bookmark(self.position()?) ) }
+
+    /// Seek back to a saved reader position.
+    #[inline] fn restore(&mut self, bookmark: Bookmark) -> Result<u64>
+        { self.seek(bookmark.0) }
+
+    /// Run `f` and restore the reader position afterwards, even on error.
+    fn with_bookmark<T>(&mut self, f: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> where Self: Sized {
+        let bookmark = self.bookmark()?;
+        let res = f(self);
+        self.restore(bookmark)?;
+        res
+    }
+
+    /// Hand a `size` byte view of the inner reader to `f`.
+    ///
+    /// The view reports end of file at the chunk boundary, and any bytes left
+    /// unread by `f` are skipped afterwards.
+    fn with_chunk_reader<T>(&mut self, size: u64, f: impl FnOnce(&mut io::Take<&mut R>) -> Result<T>) -> Result<T> where R: Read {
+        let mut reader = self.chunk_reader(size);
+        let value = f(&mut reader)?;
+        let remainder = reader.limit();
+        self.skip(remainder)?;
+        Ok( value )
+    }
 }
 
 /// The `ParserDepth` trait can be used to track depth.
@@ -88,26 +323,86 @@ pub trait ParserDepth {
     /// Get the current parser depth.
     fn depth(&mut self) -> u8 { *self.inner_depth() }
 
+    /// Maximum parser depth, defaults to 255.
+    fn max_depth(&self) -> u8 { u8::MAX }
+
     /// Increment the parser depth.
     #[inline] fn push(&mut self) { *self.inner_depth() += 1; }
 
-    /// Decrement the parser depth.
-    #[inline] fn pop(&mut self) { *self.inner_depth() -= 1; }
+    /// Increment the parser depth unless it would exceed `max_depth()`.
+    #[inline] fn try_push(&mut self) -> Result<()> {
+        if self.depth() >= self.max_depth() { return Err(Error::DepthExceeded) }
+        self.push();
+        Ok(())
+    }
+
+    /// Decrement the parser depth, saturating at zero.
+    #[inline] fn pop(&mut self) {
+        let depth = self.inner_depth();
+        debug_assert!(*depth > 0, "ParserDepth::pop called more times than push");
+        *depth = depth.saturating_sub(1);
+    }
 }
 
 /// The `ParserPath` trait accesses the the file path.
 ///
 /// It can be useful to know where a resource was loaded from, not least of all
 /// for debugging purposes. This trait adds access to the original location used
-/// to create the parser.
+/// to create the parser. Parsers constructed without a file should use an empty
+/// `PathBuf`, while `ParserOpen::from_file` receives the path to store.
+#[cfg(feature = "std")]
 pub trait ParserPath {
     /// Access the parser file path.
     fn path(&self) -> &PathBuf;
 }
 
+/// The `ParserOpen` trait opens file backed parsers.
+///
+/// Opening a file path with buffering is the most common entry point for a
+/// tool. Parsers over `BufReader<File>` implement `from_file` and get `open`,
+/// which maps a failed open through `Error::IoError`.
+#[cfg(feature = "std")]
+pub trait ParserOpen: Sized {
+    /// Construct the parser from a buffered file and the path it was opened from.
+    fn from_file(reader: std::io::BufReader<std::fs::File>, path: PathBuf) -> Self;
+
+    /// Buffer capacity used by `open`, defaults to 64 KiB.
+    fn buffer_capacity() -> usize { 64 * 1024 }
+
+    /// Open a file path with buffering.
+    #[inline] fn open<P: AsRef<Path>>(path: P) -> Result<Self>
+        { Self::open_with_capacity(path, Self::buffer_capacity()) }
+
+    /// Open a file path with a buffer of `capacity` bytes.
+    ///
+    /// Larger buffers mean fewer refills when parsing many small chunks.
+    fn open_with_capacity<P: AsRef<Path>>(path: P, capacity: usize) -> Result<Self> {
+        let file = std::fs::File::open(path.as_ref())?;
+        Ok( Self::from_file(std::io::BufReader::with_capacity(capacity, file), path.as_ref().to_path_buf()) )
+    }
+}
+
+/// The `ParserMmap` trait opens memory mapped parsers.
+///
+/// Large files parse faster from memory than through buffered reads. Parsers
+/// over `Cursor<Mmap>` implement `from_mmap` and get `mmap`. The file must not
+/// be modified by another process while it is mapped.
+#[cfg(feature = "mmap")]
+pub trait ParserMmap: Sized {
+    /// Construct the parser from a memory map and the path it was opened from.
+    fn from_mmap(reader: io::Cursor<memmap2::Mmap>, path: PathBuf) -> Self;
+
+    /// Memory map a file path.
+    fn mmap<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let file = std::fs::File::open(path.as_ref())?;
+        let map = unsafe { memmap2::Mmap::map(&file)? }; // file must not change while mapped
+        Ok( Self::from_mmap(io::Cursor::new(map), path.as_ref().to_path_buf()) )
+    }
+}
+
 /// The `ParserRead` trait provides typed read API.
 ///
-/// The `ParserRead` API uses `read_uninit()` to read any sized type directly
+/// The `ParserRead` API uses `read_uninit()` to read any `Pod` type directly
 /// into uninitialised memory.
 ///
 /// Block chunk file formats like IFF often layout their data in such a way that
@@ -116,206 +411,3184 @@ pub trait ParserPath {
 /// smaller variables. Use `ParserRead` in conjunction with packed struct types
 /// like those defined in `esm_bindings`.
 pub trait ParserRead<R: Read>: ParserReader<R> {
-    /// Read a sized type from the reader into uninitialised memory.
-    #[inline] fn read<T: Sized>(&mut self) -> Result<T>
-        { self.reader().read_uninit() }
-
-    /// Big endian read for all primitive integer types.
-    #[inline] fn read_be<T: PrimInt>(&mut self) -> Result<T>
-        { Ok( T::swap_bytes(self.reader().read_uninit()?) ) }
-}
-
-//------------------------------------------------------------------------------
+    /// Read a plain old data type from the reader into uninitialised memory.
+    ///
+    /// Debug builds with the `tracing` feature log the type name and size when
+    /// the reader ends part way through the value. A reader ending cleanly
+    /// before the first byte, as at the end of a chunk stream, is not logged.
+    #[inline] fn read<T: Pod>(&mut self) -> Result<T> {
+        #[cfg(all(debug_assertions, feature = "tracing"))] {
+            let mut consumed = 0;
+            let res = TapReader::new(self.reader(), |bytes: &[u8]| consumed += bytes.len()).read_uninit();
+            if let (Err(Error::UnexpectedEof), 1..) = (&res, consumed) {
+                tracing::debug!(type_name = core::any::type_name::<T>(), size = core::mem::size_of::<T>(), consumed, "read ended part way through a value");
+            }
+            res
+        }
+        #[cfg(not(all(debug_assertions, feature = "tracing")))]
+        self.reader().read_uninit()
+    }
 
-/// The `ReaderUninit` trait adds a typed read function.
-pub trait ReaderUninit<T: Sized> {
-    fn read_uninit(&mut self) -> Result<T>;
-}
+    /// Read any sized type from the reader into uninitialised memory.
+    ///
+    /// # Safety
+    ///
+    /// The bytes read must form a valid value of `T`, see `Pod`.
+    #[inline] unsafe fn read_unchecked<T: Sized>(&mut self) -> Result<T>
+        { self.reader().read_uninit_unchecked() }
 
-// Blanket implementation of typed read.
-impl<R: Read, T: Sized> ReaderUninit<T> for R {
-    fn read_uninit(&mut self) -> Result<T> {
-        let mut uninit = MaybeUninit::<T>::uninit(); // allocate memory
-        Ok( unsafe { // read directly into pointer
-            let ptr = uninit.as_mut_ptr();
-            self.read_exact(std::slice::from_raw_parts_mut(ptr as *mut u8, std::mem::size_of::<T>()))?;
-            uninit.assume_init() // confirm initialisation
-        } )
+    /// Read a sized type without exceeding the `remaining` chunk bytes.
+    #[inline] fn read_bounded<T: Pod>(&mut self, remaining: &mut u64) -> Result<T> {
+        let size = core::mem::size_of::<T>() as u64;
+        if size > *remaining { return Err(Error::ChunkOverrun) }
+        let value = self.read()?;
+        *remaining -= size;
+        Ok( value )
     }
-}
 
-//------------------------------------------------------------------------------
+    /// Fill a caller owned byte buffer.
+    #[inline] fn read_into(&mut self, buf: &mut [u8]) -> Result<()>
+        { Ok( self.reader().read_exact(buf)? ) }
 
-/// Dummy constructor trait for reader types.
-pub trait DummyReader {
-    fn dummy() -> Self;
-}
+    /// Read a runtime sized byte buffer.
+    ///
+    /// The buffer grows with the bytes actually read, so a length read from a
+    /// truncated or crafted file returns `Error::UnexpectedEof` without first
+    /// allocating `len` bytes.
+    fn read_bytes(&mut self, len: usize) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        self.reader().take(to_size(len)?).read_to_end(&mut buf)?;
+        if buf.len() != len { return Err(Error::UnexpectedEof) }
+        Ok( buf )
+    }
 
-impl DummyReader for std::io::BufReader<std::fs::File> {
-    fn dummy() -> std::io::BufReader<std::fs::File> {
-        let file = std::fs::File::open("dummy.txt").unwrap();
-        std::io::BufReader::new(file)
+    /// Stream exactly `size` bytes into `writer`, e.g. a hasher or decoder.
+    ///
+    /// Returns the number of bytes copied, a reader ending early returns
+    /// `Error::UnexpectedEof`.
+    #[cfg(feature = "std")]
+    fn copy_to<W: Write + ?Sized>(&mut self, size: u64, writer: &mut W) -> Result<u64> {
+        let copied = std::io::copy(&mut self.reader().take(size), writer)?;
+        if copied != size { return Err(Error::UnexpectedEof) }
+        Ok( copied )
     }
-}
 
-//------------------------------------------------------------------------------
+    /// Read a fixed count array of sized types.
+    ///
+    /// The array is only initialised once every element has been read, a short
+    /// read returns an error instead of a partially filled array.
+    #[inline] fn read_array<T: Pod, const N: usize>(&mut self) -> Result<[T; N]>
+        { self.read() }
 
-/// The `HeaderParser` trait defines unique header parsing logic.
-pub trait HeaderParser<H> {
-    fn header(&mut self) -> Result<H>;
-}
+    /// Big endian read for fixed count arrays of primitive integer types.
+    #[inline] fn read_array_be<T: PrimInt + Pod, const N: usize>(&mut self) -> Result<[T; N]>
+        { Ok( self.read_array::<T, N>()?.map(T::from_be) ) }
 
-/// Signature for parser closures.
-pub type ParserFn<P,H> = fn(parser: &mut P, header: &H) -> Result<u64>;
+    /// Read a runtime count of sized types into a vector.
+    ///
+    /// A count read from an untrusted file that cannot be allocated returns
+    /// `Error::SizeOverflow` instead of aborting.
+    fn read_vec<T: Pod>(&mut self, count: usize) -> Result<Vec<T>> {
+        let size = count.checked_mul(core::mem::size_of::<T>())
+            .filter(|size| *size <= isize::MAX as usize)
+            .ok_or(Error::SizeOverflow)?;
+        let mut vec = Vec::<T>::new();
+        vec.try_reserve_exact(count).map_err(|_| Error::SizeOverflow)?; // allocate memory
+        unsafe { // read directly into the vector buffer
+            let ptr = vec.as_mut_ptr();
+            self.reader().read_exact(core::slice::from_raw_parts_mut(ptr as *mut u8, size))?;
+            vec.set_len(count); // confirm initialisation
+        }
+        Ok( vec )
+    }
 
-/// The `ChunkParser` trait defines the inner parser loop.
-pub trait ChunkParser<R: Read + Seek>: ParserRead<R> + ParserDepth {
-    /// Internal parser loop.
-    fn parse_loop<H>(&mut self, f: ParserFn<Self,H>, total_size: u64) -> Result<()> where Self: HeaderParser<H> {
+    /// Big endian read for a runtime count of primitive integer types.
+    #[inline] fn read_vec_be<T: PrimInt + Pod>(&mut self, count: usize) -> Result<Vec<T>>
+        { Ok( self.read_vec::<T>(count)?.into_iter().map(T::from_be).collect() ) }
+
+    /// Read the bytes of a null terminated string, consuming the terminator.
+    fn read_cstring_bytes(&mut self) -> Result<Vec<u8>> {
+        let mut bytes = Vec::new();
         loop {
-            let header = self.header()?;
-            let start = self.reader().stream_position()?;
-            let size = f(self, &header)?; // the parser function is responsible for parsing the size
-            let end = start + size;
-            let pos = self.reader().stream_position()?;
-            if pos == total_size { break Ok(()) } // function consumed chunk
-            else if pos != end { break Err(Error::ParseError) } // function made a mistake
+            match self.read::<u8>()? {
+                0 => break Ok( bytes ),
+                byte => bytes.push(byte)
+            }
         }
     }
 
-    /// Parse top level chunk(s) from the reader.
-    #[inline]
-    fn parse<H>(&mut self, f: ParserFn<Self,H>) -> Result<()> where Self: HeaderParser<H> {
-        let total_size = self.reader().seek(SeekFrom::End(0))?;
-        self.reader().seek(SeekFrom::Start(0))?;
-        self.parse_loop(f, total_size)
+    /// Read a null terminated UTF-8 string, consuming the terminator.
+    #[inline] fn read_cstring(&mut self) -> Result<String>
+        { String::from_utf8(self.read_cstring_bytes()?).map_err(|_| Error::InvalidUtf8) }
+
+    /// Read the untrimmed bytes of a fixed length string.
+    #[inline] fn read_string_raw(&mut self, len: usize) -> Result<Vec<u8>>
+        { self.read_bytes(len) }
+
+    /// Read a fixed length UTF-8 string, trimming trailing null and space padding.
+    fn read_string(&mut self, len: usize) -> Result<String> {
+        let mut bytes = self.read_string_raw(len)?;
+        let trimmed = bytes.iter().rposition(|b| *b != 0x00 && *b != 0x20).map_or(0, |i| i + 1);
+        bytes.truncate(trimmed);
+        String::from_utf8(bytes).map_err(|_| Error::InvalidUtf8)
     }
 
-    /// Parse nested subchunks within the main parse routine.
-    #[inline]
-    fn subchunks<H>(&mut self, f: ParserFn<Self,H>, total_size: u64) -> Result<()> where Self: HeaderParser<H> {
-        self.push();
-        match {
-            let pos = self.reader().stream_position()?;
-            self.parse_loop(f, pos + total_size)
-        } {
-            res => { self.pop(); res }
+    /// Read a length prefixed UTF-8 string.
+    fn read_pascal_string(&mut self) -> Result<String> {
+        let len = self.read::<u8>()?;
+        String::from_utf8(self.read_bytes(len as usize)?).map_err(|_| Error::InvalidUtf8)
+    }
+
+    /// Read a length prefixed UTF-8 string padded to an even total length.
+    fn read_pascal_string_padded(&mut self) -> Result<String> {
+        let string = self.read_pascal_string()?;
+        if string.len() % 2 == 0 { self.read::<u8>()?; } // length byte makes the total odd
+        Ok( string )
+    }
+
+    /// Read a four character code.
+    #[inline] fn read_fourcc(&mut self) -> Result<FourCC>
+        { self.read() }
+
+    /// Read a four character code, rejecting bytes outside printable ASCII.
+    fn read_fourcc_checked(&mut self) -> Result<FourCC> {
+        let bytes = self.read_array::<u8, 4>()?;
+        if !bytes.iter().all(|b| (0x20..=0x7e).contains(b)) { return Err(Error::InvalidFourCC) }
+        (&bytes[..]).read_uninit()
+    }
+
+    /// Read a `bytemuck` plain old data type.
+    #[cfg(feature = "bytemuck")]
+    fn read_pod<T: bytemuck::AnyBitPattern>(&mut self) -> Result<T> {
+        let buf = self.read_bytes(core::mem::size_of::<T>())?;
+        Ok( bytemuck::pod_read_unaligned(&buf) )
+    }
+
+    /// Read a runtime count of `bytemuck` plain old data types.
+    #[cfg(feature = "bytemuck")]
+    fn read_pod_slice<T: bytemuck::AnyBitPattern>(&mut self, count: usize) -> Result<Vec<T>> {
+        let size = core::mem::size_of::<T>();
+        if size == 0 { return Ok( alloc::vec![T::zeroed(); count] ) }
+        let buf = self.read_bytes(count.checked_mul(size).ok_or(Error::SizeOverflow)?)?;
+        Ok( buf.chunks_exact(size).map(bytemuck::pod_read_unaligned).collect() )
+    }
+
+    /// Read a standard header with the size in the given byte order.
+    #[inline] fn read_standard_header(&mut self, endian: Endian) -> Result<StandardHeader>
+        { Ok( StandardHeader { fourcc: self.read_fourcc()?, size: self.read_int(endian)? } ) }
+
+    /// Read a standard header, converting the size to the body size.
+    ///
+    /// A size covering the header is reduced by `StandardHeader::SIZE`, other
+    /// header layouts call `SizeConvention::body_size` with their own width.
+    fn read_standard_header_with(&mut self, endian: Endian, convention: SizeConvention) -> Result<StandardHeader> {
+        let header = self.read_standard_header(endian)?;
+        let size = convention.body_size(header.size as u64, StandardHeader::SIZE)?;
+        Ok( StandardHeader { size: size as u32, ..header } )
+    }
+
+    /// Big endian read for all primitive integer types.
+    #[inline] fn read_be<T: PrimInt + Pod>(&mut self) -> Result<T>
+        { Ok( T::from_be(self.read()?) ) }
+
+    /// Little endian read for all primitive integer types.
+    #[inline] fn read_le<T: PrimInt + Pod>(&mut self) -> Result<T>
+        { Ok( T::from_le(self.read()?) ) }
+
+    /// Runtime endian read for all primitive integer types.
+    #[inline] fn read_int<T: PrimInt + Pod>(&mut self, endian: Endian) -> Result<T> {
+        match endian {
+            Endian::Little => self.read_le(),
+            Endian::Big => self.read_be()
         }
     }
-}
 
-//------------------------------------------------------------------------------
+    /// Read a plain old data type, feeding its bytes into `hasher`.
+    fn read_hashed<T: Pod, H: core::hash::Hasher>(&mut self, hasher: &mut H) -> Result<T> {
+        let value = self.read::<T>()?;
+        // `Pod` types have no padding, so every byte of the value is initialised
+        let bytes = unsafe { core::slice::from_raw_parts(&value as *const T as *const u8, core::mem::size_of::<T>()) };
+        hasher.write(bytes);
+        Ok( value )
+    }
 
-/// `chunk_parser` prelude.
-pub mod prelude {
-    pub use super::{FourCC, TypeId};
-    pub use super::{
-        HeaderParser, ChunkParser,
-        ParserReader, ParserRead, ParserSeek,
-        ParserDepth, ParserPath,
-        ParserFn
-    };
-    pub use super::chunk_parser;
-}
+    /// Big endian read for `f32`.
+    #[inline] fn read_f32_be(&mut self) -> Result<f32>
+        { Ok( f32::from_bits(self.read_be()?) ) }
 
-//==============================================================================
+    /// Little endian read for `f32`.
+    #[inline] fn read_f32_le(&mut self) -> Result<f32>
+        { Ok( f32::from_bits(self.read_le()?) ) }
 
-#[cfg(test)]
-mod tests {
-    mod chunk_parser {
-        pub use super::super::Error;
-        pub use super::super::Result;
+    /// Big endian read for `f64`.
+    #[inline] fn read_f64_be(&mut self) -> Result<f64>
+        { Ok( f64::from_bits(self.read_be()?) ) }
+
+    /// Little endian read for `f64`.
+    #[inline] fn read_f64_le(&mut self) -> Result<f64>
+        { Ok( f64::from_bits(self.read_le()?) ) }
+
+    /// Read a big endian 80-bit IEEE extended float, as used for AIFF sample
+    /// rates, rounding it to the nearest `f64`.
+    fn read_f80_be(&mut self) -> Result<f64> {
+        let sign_exponent = self.read_be::<u16>()?;
+        let mantissa = self.read_be::<u64>()?; // explicit integer bit
+        let sign = if sign_exponent & 0x8000 != 0 { -1.0 } else { 1.0 };
+        let exponent = (sign_exponent & 0x7fff) as i32;
+        Ok( sign * match exponent {
+            _ if mantissa == 0 => 0.0,
+            0x7fff if mantissa << 1 == 0 => f64::INFINITY,
+            0x7fff => f64::NAN,
+            _ => mantissa as f64 * 2f64.powi(exponent.max(1) - 16383 - 63) // zero exponent is denormal
+        } )
     }
-    use super::prelude::*;
-    use chunk_parser::{Error, Result};
 
-    // full iff parser definition without macro
-    use std::io::{Read, Seek};
-    struct IFFParserFull<R> { reader: R, depth: u8 }
-    impl<R: Read> IFFParserFull<R> { fn new(reader: R) -> IFFParserFull<R> { IFFParserFull{ reader, depth: 0 } } }
-    impl<R> ParserReader<R> for IFFParserFull<R> { fn reader(&mut self) -> &mut R { &mut self.reader } }
-    impl<R: Seek> ParserSeek<R> for IFFParserFull<R> {}
-    impl<R: Read> ParserRead<R> for IFFParserFull<R> {}
-    impl<R> ParserDepth for IFFParserFull<R> { fn inner_depth(&mut self) -> &mut u8 { &mut self.depth } }
-    impl<R: Read + Seek> ChunkParser<R> for IFFParserFull<R> {}
+    /// Read a one byte boolean, any nonzero value is `true`.
+    ///
+    /// `bool` is not `Pod` since most byte values are invalid for it, this
+    /// reads a `u8` instead.
+    #[inline] fn read_bool(&mut self) -> Result<bool>
+        { Ok( self.read::<u8>()? != 0 ) }
 
-    // Simple header definition.
-    struct IFFHeader { typeid: TypeId, length: u32 }
-    impl<R: Read> HeaderParser<IFFHeader> for IFFParserFull<R> {
-        fn header(&mut self) -> Result<IFFHeader>
-            { Ok( IFFHeader { typeid: self.read()?, length: self.read_be()? } ) }
+    /// Read an integer discriminant of type `I` and convert it to `T`.
+    ///
+    /// Enums are not `Pod` either, so the conversion goes through `TryFrom`.
+    /// Unknown discriminants return `Error::ParseError`.
+    #[inline] fn read_enum<T: TryFrom<I>, I: PrimInt + Pod>(&mut self, endian: Endian) -> Result<T>
+        { T::try_from(self.read_int::<I>(endian)?).map_err(|_| Error::ParseError) }
+
+    /// Big endian read for plain old data structs, swapping every field.
+    #[inline] fn read_struct_be<T: Pod + ByteSwap>(&mut self) -> Result<T> {
+        let value = self.read::<T>()?;
+        Ok( if cfg!(target_endian = "little") { value.swap_bytes() } else { value } )
     }
 
-    // minimal iff parser definition with macro
-    #[chunk_parser]
-    struct IFFParser;
-    impl<R: Read> HeaderParser<IFFHeader> for IFFParser<R> {
-        fn header(&mut self) -> Result<IFFHeader>
-            { Ok( IFFHeader { typeid: self.read()?, length: self.read_be()? } ) }
+    /// Little endian read for plain old data structs, swapping every field.
+    #[inline] fn read_struct_le<T: Pod + ByteSwap>(&mut self) -> Result<T> {
+        let value = self.read::<T>()?;
+        Ok( if cfg!(target_endian = "big") { value.swap_bytes() } else { value } )
     }
 
-    // minimal custom parser loop
-    #[chunk_parser(custom,depth)]
-    struct IFFParserCustom;
-    impl<R: Read> HeaderParser<IFFHeader> for IFFParserCustom<R> {
-        fn header(&mut self) -> Result<IFFHeader>
-            { Ok( IFFHeader { typeid: self.read()?, length: self.read_be::<u32>()? - 8 } ) }
+    /// Read an unsigned integer of up to 8 bytes, e.g. 24 bit PCM samples.
+    ///
+    /// More than 8 bytes returns `Error::SizeOverflow`.
+    fn read_uint(&mut self, bytes: usize, endian: Endian) -> Result<u64> {
+        if bytes > 8 { return Err(Error::SizeOverflow) }
+        let mut buf = [0; 8];
+        self.read_into(&mut buf[..bytes])?;
+        Ok( match endian {
+            Endian::Little => buf[..bytes].iter().rev().fold(0, |value, &byte| value << 8 | byte as u64),
+            Endian::Big => buf[..bytes].iter().fold(0, |value, &byte| value << 8 | byte as u64)
+        } )
     }
-    impl<R: std::io::Read + std::io::Seek> ChunkParser<R> for IFFParserCustom<R> {
-        fn parse_loop<H>(&mut self, f: ParserFn<Self,H>, total_size: u64) -> Result<()> where Self: HeaderParser<H> {
-            self.push();
-            match loop {
-                let header = self.header()?;
-                let start = self.reader().stream_position()?;
-                let size = f(self, &header)? + 8; // the parser function is responsible for parsing the size
-                let end = start + size;
-                let pos = self.reader().stream_position()?;
-                if pos == total_size { break Ok(()) } // function consumed chunk
-                else if pos != end { break Err(Error::ParseError) } // function made a mistake
-            } {
-                res => { self.pop(); res }
+
+    /// Read a sign extended integer of up to 8 bytes.
+    ///
+    /// More than 8 bytes returns `Error::SizeOverflow`.
+    fn read_sint(&mut self, bytes: usize, endian: Endian) -> Result<i64> {
+        let value = self.read_uint(bytes, endian)?;
+        if bytes == 0 { return Ok( 0 ) }
+        let shift = 64 - 8 * bytes as u32;
+        Ok( (value << shift) as i64 >> shift )
+    }
+
+    /// Little endian read for 24 bit unsigned integers.
+    #[inline] fn read_u24_le(&mut self) -> Result<u32>
+        { Ok( self.read_uint(3, Endian::Little)? as u32 ) }
+
+    /// Big endian read for 24 bit unsigned integers.
+    #[inline] fn read_u24_be(&mut self) -> Result<u32>
+        { Ok( self.read_uint(3, Endian::Big)? as u32 ) }
+
+    /// Read a 4 byte synchsafe integer holding 7 bits per byte.
+    ///
+    /// Synchsafe integers are used by ID3v2 tags, a set high bit in any byte
+    /// returns `Error::ParseError`.
+    #[inline] fn read_synchsafe(&mut self) -> Result<u32>
+        { Ok( synchsafe(&self.read_array::<u8, 4>()?)? as u32 ) }
+
+    /// Read a 5 byte synchsafe integer holding 35 bits.
+    #[inline] fn read_synchsafe35(&mut self) -> Result<u64>
+        { synchsafe(&self.read_array::<u8, 5>()?) }
+
+    /// Read an unsigned LEB128 variable length integer.
+    ///
+    /// Encodings that do not fit in 64 bits return `Error::SizeOverflow`.
+    fn read_uleb128(&mut self) -> Result<u64> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.read::<u8>()?;
+            let bits = (byte & 0x7f) as u64;
+            if shift == 63 && bits > 1 { return Err(Error::SizeOverflow) }
+            value |= bits << shift;
+            if byte & 0x80 == 0 { return Ok( value ) }
+        }
+        Err(Error::SizeOverflow) // continuation past the 10th byte
+    }
+
+    /// Read a signed LEB128 variable length integer.
+    ///
+    /// Encodings that do not fit in 64 bits return `Error::SizeOverflow`.
+    fn read_sleb128(&mut self) -> Result<i64> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.read::<u8>()?;
+            let bits = (byte & 0x7f) as i64;
+            if shift == 63 && bits != 0 && bits != 0x7f { return Err(Error::SizeOverflow) }
+            value |= bits << shift;
+            if byte & 0x80 == 0 {
+                if shift < 57 && byte & 0x40 != 0 { value |= -1 << (shift + 7) } // sign extend
+                return Ok( value )
             }
         }
+        Err(Error::SizeOverflow) // continuation past the 10th byte
     }
+}
 
-    // nonsense data to test basic functionality
-    const DATA: &[u8;24] = &[
-        // FORM chunk (24 bytes)
-        0x46, 0x4f, 0x52, 0x4d, // "FORM" chunk typeid
-        0x00, 0x00, 0x00, 0x10, // Chunk size (16 bytes)
-        0x54, 0x45, 0x53, 0x54, // Subchunk typeid ("TEST")
+// Decode big endian synchsafe bytes, rejecting a set high bit.
+fn synchsafe(bytes: &[u8]) -> Result<u64> {
+    bytes.iter().try_fold(0, |value, &byte| {
+        if byte & 0x80 != 0 { return Err(Error::ParseError) }
+        Ok( value << 7 | byte as u64 )
+    })
+}
 
-        // TEST chunk (12 bytes)
-        0x54, 0x45, 0x53, 0x54, // "TEST" chunk typid
-        0x00, 0x00, 0x00, 0x04, // Chunk size (4 bytes)
-        0x01, 0x02, 0x03, 0x04, // Test data
-    ];
+/// The `ParserEndian` trait stores a default byte order.
+///
+/// Some formats like TIFF declare their byte order in a marker at the start of
+/// the file, after which every integer field follows that order. This trait
+/// adds API for switching the byte order at runtime, so parser closures can
+/// read integers without threading an `Endian` value through every call.
+pub trait ParserEndian<R: Read>: ParserRead<R> {
+    /// Access the inner endian property.
+    fn inner_endian(&mut self) -> &mut Endian;
 
-    #[test]
-    fn without_macro() -> Result<()> {
-        let mut cursor = std::io::Cursor::new(DATA);
-        let mut iff = IFFParserFull::new(&mut cursor);
-        iff.parse(|parser, header| {
-            if &header.typeid != b"FORM" { panic!(); }
-            parser.skip(header.length as u64)
-        })
+    /// Get the current parser byte order.
+    fn endian(&mut self) -> Endian { *self.inner_endian() }
+
+    /// Change the parser byte order.
+    #[inline] fn set_endian(&mut self, endian: Endian) { *self.inner_endian() = endian; }
+
+    /// Read a primitive integer type in the current parser byte order.
+    #[inline] fn read_endian<T: PrimInt + Pod>(&mut self) -> Result<T> {
+        let endian = self.endian();
+        self.read_int(endian)
     }
+}
 
-    #[test]
-    fn with_macro() -> Result<()> {
-        let mut cursor = std::io::Cursor::new(DATA);
-        let mut iff = IFFParser::new(&mut cursor);
-        iff.parse(|parser, header| parser.skip(header.length as u64))
+/// The `ParserSize` trait declares the width of chunk size fields.
+///
+/// Size fields vary between formats, from `u16` in older formats through `u32`
+/// for IFF and RIFF to `u64` for RF64 and W64. This trait declares the size
+/// type and byte order once so `header()` implementations can read the size
+/// field with `read_size()` and always work in `u64`.
+pub trait ParserSize<R: Read>: ParserRead<R> {
+    /// Primitive integer type of the size field.
+    type ChunkSize: PrimInt + Pod + Into<u64>;
+
+    /// Byte order of the size field.
+    fn size_endian(&self) -> Endian { Endian::Big }
+
+    /// Read a size field and widen it to `u64`.
+    #[inline] fn read_size(&mut self) -> Result<u64> {
+        let endian = self.size_endian();
+        Ok( self.read_int::<Self::ChunkSize>(endian)?.into() )
     }
+}
 
-    #[test]
-    fn cursor() -> Result<()> {
-        IFFParser::cursor(DATA).parse(|parser, header| parser.skip(header.length as u64))
+/// The `ParserPeek` trait reads ahead without advancing the reader.
+///
+/// Deciding how to interpret an upcoming chunk often requires looking at the
+/// next few bytes first. This trait is implemented for every parser that can
+/// both read and seek, and always restores the original position even when the
+/// read itself fails.
+pub trait ParserPeek<R: Read + Seek>: ParserRead<R> + ParserSeek<R> {
+    /// Read a sized type without advancing the reader.
+    fn peek<T: Pod>(&mut self) -> Result<T> {
+        let pos = self.position()?;
+        let res = self.read();
+        self.seek(pos)?;
+        res
     }
 
-    #[test]
-    fn custom() -> Result<()> {
-        let mut iff = IFFParserCustom::cursor(DATA);
-        iff.parse(|parser, header| parser.skip(header.length as u64 + 8))
+    /// Read a four character code without advancing the reader.
+    #[inline] fn peek_fourcc(&mut self) -> Result<FourCC>
+        { self.peek() }
+
+    /// Read a sized type at an absolute offset without moving the reader.
+    fn read_at<T: Pod>(&mut self, offset: u64) -> Result<T> {
+        let pos = self.position()?;
+        let res = self.seek(offset).and_then(|_| self.read());
+        self.seek(pos)?;
+        res
+    }
+
+    /// Fill a byte buffer from an absolute offset without moving the reader.
+    fn read_exact_at(&mut self, offset: u64, buf: &mut [u8]) -> Result<()> {
+        let pos = self.position()?;
+        let res = self.seek(offset).and_then(|_| self.read_into(buf));
+        self.seek(pos)?;
+        res
+    }
+}
+
+// Blanket implementation of peek.
+impl<R: Read + Seek, P: ParserRead<R> + ParserSeek<R>> ParserPeek<R> for P {}
+
+/// The `ParserSlice` trait borrows bytes straight from an in-memory reader.
+///
+/// Parsers over a `Cursor` of bytes, including `Cursor<&[u8]>`, `Cursor<Vec<u8>>`
+/// and the memory mapped reader, can hand out chunk bodies as slices of the
+/// backing buffer instead of copying them into a `Vec`.
+pub trait ParserSlice<T: AsRef<[u8]>>: ParserReader<io::Cursor<T>> {
+    /// Borrow the next `len` bytes and advance past them.
+    ///
+    /// Fewer than `len` remaining bytes returns `Error::UnexpectedEof` without
+    /// moving the reader.
+    fn read_slice<'a>(&'a mut self, len: usize) -> Result<&'a [u8]> where T: 'a {
+        let cursor = self.reader();
+        let start = to_usize(cursor.position())?;
+        let end = start.checked_add(len).ok_or(Error::SizeOverflow)?;
+        if end > cursor.get_ref().as_ref().len() { return Err(Error::UnexpectedEof) }
+        cursor.set_position(end as u64);
+        Ok( &cursor.get_ref().as_ref()[start..end] )
+    }
+
+    /// Borrow every byte from the reader position to the end of the buffer.
+    fn remaining_slice<'a>(&'a mut self) -> &'a [u8] where T: 'a {
+        let cursor = self.reader();
+        let data = cursor.get_ref().as_ref();
+        let start = usize::try_from(cursor.position()).map_or(data.len(), |pos| pos.min(data.len()));
+        &data[start..]
+    }
+}
+
+// Blanket implementation of slice access.
+impl<T: AsRef<[u8]>, P: ParserReader<io::Cursor<T>>> ParserSlice<T> for P {}
+
+/// The `ParserCrc` trait validates CRC-32 checksums.
+///
+/// PNG and similar formats end each chunk with a CRC-32 over the bytes that
+/// came before it. Verification re-reads the covered range, so this trait is
+/// implemented for every parser that can both read and seek, and always
+/// restores the original position afterwards.
+#[cfg(feature = "crc")]
+pub trait ParserCrc<R: Read + Seek>: ParserRead<R> + ParserSeek<R> {
+    /// Read a big endian CRC-32 value.
+    #[inline] fn read_crc32(&mut self) -> Result<u32>
+        { self.read_be() }
+
+    /// Compute the CRC-32 of `len` bytes at `start` and compare to `expected`.
+    fn verify_crc32(&mut self, start: u64, len: u64, expected: u32) -> Result<()> where Self: Sized {
+        self.with_bookmark(|parser| {
+            parser.seek(start)?;
+            let mut hasher = crc32fast::Hasher::new();
+            let mut reader = parser.chunk_reader(len);
+            let mut buf = [0; 4096];
+            let mut remaining = len;
+            while remaining > 0 {
+                let n = reader.read(&mut buf)?;
+                if n == 0 { return Err(Error::UnexpectedEof) }
+                hasher.update(&buf[..n]);
+                remaining -= n as u64;
+            }
+            let actual = hasher.finalize();
+            if actual != expected { return Err(Error::ChecksumMismatch { expected, actual }) }
+            Ok(())
+        })
+    }
+}
+
+// Blanket implementation of crc.
+#[cfg(feature = "crc")]
+impl<R: Read + Seek, P: ParserRead<R> + ParserSeek<R>> ParserCrc<R> for P {}
+
+/// The `ParserAlign` trait defines chunk alignment.
+///
+/// Formats like RIFF and AIFF pad chunks to an even boundary, while some game
+/// archives align to 4 or 16 bytes. This trait declares the alignment of the
+/// format and adds API for skipping the pad bytes after a chunk body. The
+/// default alignment of 1 never skips anything.
+pub trait ParserAlign<R: Seek>: ParserSeek<R> {
+    /// Chunk alignment in bytes, must be non-zero.
+    fn alignment(&self) -> u64 { 1 }
+
+    /// Skip forward to the next aligned position relative to `start`.
+    ///
+    /// Returns the number of pad bytes skipped.
+    fn align(&mut self, start: u64) -> Result<u64> {
+        let len = self.position()?.checked_sub(start).ok_or(Error::ParseError)?;
+        let pad = len.checked_next_multiple_of(self.alignment()).ok_or(Error::SizeOverflow)? - len;
+        if pad > 0 { self.skip(pad)?; }
+        Ok( pad )
+    }
+}
+
+/// The `ParserChunk` trait tracks the end of the current chunk.
+///
+/// Chunks holding a variable number of records are read until the end of the
+/// chunk body is reached. This trait stores the body end recorded by the
+/// tracked parser loops so closures can query how many bytes are left.
+pub trait ParserChunk<R: Seek>: ParserSeek<R> {
+    /// Access the inner chunk end property.
+    fn inner_chunk_end(&mut self) -> &mut u64;
+
+    /// Get the end position of the current chunk body.
+    fn chunk_end(&mut self) -> u64 { *self.inner_chunk_end() }
+
+    /// Get the number of bytes left in the current chunk body.
+    fn chunk_remaining(&mut self) -> Result<u64> {
+        let end = self.chunk_end();
+        end.checked_sub(self.position()?).ok_or(Error::ParseError)
+    }
+
+    /// Skip the remainder of the current chunk body.
+    #[inline] fn skip_chunk_remaining(&mut self) -> Result<u64> {
+        let end = self.chunk_end();
+        self.skip_to_end(end)
+    }
+}
+
+/// Chunk statistics collected by `ParserStats::charge_chunk`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ParseStats {
+    pub chunk_count: u64, // Number of chunks parsed at any depth.
+    pub max_depth: u8, // Deepest nesting level reached.
+    pub bytes_parsed: u64, // Bytes covered by top level chunks.
+    pub bytes_visited: u64 // Bytes covered by chunks at any depth.
+}
+
+/// The `ParserStats` trait accumulates chunk statistics.
+///
+/// Validation tools often need to know how many chunks a file holds or how
+/// deeply they nest. This trait stores a `ParseStats` updated by `charge_chunk`,
+/// so closures don't have to be instrumented by hand. Implementors override
+/// `ChunkParser::charge` with `self.charge_chunk(context, bytes)`, so every
+/// parser loop enforces `max_chunks` and `max_bytes`, bounding the work a
+/// crafted file from an untrusted source can cause.
+pub trait ParserStats: ParserDepth {
+    /// Get the statistics collected so far.
+    fn stats(&self) -> &ParseStats;
+
+    /// Access the statistics for updating.
+    fn stats_mut(&mut self) -> &mut ParseStats;
+
+    /// Maximum number of chunks the counted loops parse, defaults to unlimited.
+    fn max_chunks(&self) -> u64 { u64::MAX }
+
+    /// Maximum number of bytes covered by chunks at any depth, defaults to
+    /// unlimited. Nested bytes count once per enclosing chunk, so the budget
+    /// also bounds deep nesting. Each chunk is charged its header and declared
+    /// body size before its handler runs.
+    fn max_bytes(&self) -> u64 { u64::MAX }
+
+    /// Count a chunk covering `bytes` bytes, enforcing the budgets.
+    ///
+    /// Top level chunks also add their bytes to `bytes_parsed`.
+    fn charge_chunk(&mut self, context: &ChunkContext, bytes: u64) -> Result<()> {
+        let stats = self.stats_mut();
+        stats.chunk_count += 1;
+        stats.max_depth = stats.max_depth.max(context.depth);
+        stats.bytes_visited = stats.bytes_visited.saturating_add(bytes);
+        if context.depth == 0 { stats.bytes_parsed = stats.bytes_parsed.saturating_add(bytes) }
+        let stats = *self.stats();
+        if stats.chunk_count > self.max_chunks() || stats.bytes_visited > self.max_bytes() { return Err(Error::BudgetExceeded) }
+        Ok(())
+    }
+}
+
+/// The `ParserTotalSize` trait caches the total size of the reader.
+///
+/// Finding the reader size requires seeking to the end and back again. This
+/// trait stores the size after the first query, so closures can compare chunk
+/// sizes against the length of the file without repeating the seek. Parsers
+/// overriding `ChunkParser::record_total_size` also keep the size found by the
+/// top level parse variants.
+pub trait ParserTotalSize<R: Seek>: ParserSeek<R> {
+    /// Get the cached total size, `None` until it is first found.
+    fn cached_total_size(&self) -> Option<u64>;
+
+    /// Access the inner total size property.
+    fn inner_total_size(&mut self) -> &mut Option<u64>;
+
+    /// Get the total size of the reader, seeking to the end on first use.
+    fn total_size(&mut self) -> Result<u64> {
+        if let Some(size) = *self.inner_total_size() { return Ok( size ) }
+        let pos = self.position()?;
+        let size = self.reader().seek(SeekFrom::End(0))?;
+        self.seek(pos)?;
+        Ok( *self.inner_total_size().insert(size) )
+    }
+}
+
+//------------------------------------------------------------------------------
+
+/// Marker trait for plain old data types.
+///
+/// Typed reads fill memory directly from the reader, which is only sound for
+/// types where every bit pattern is a valid value. Packed struct layouts built
+/// from primitive integers, floats and arrays can opt in with an unsafe impl.
+///
+/// # Safety
+///
+/// Implementors must be valid for any combination of bytes of their size and
+/// contain no padding bytes. Types containing `bool`, `char`, enums, references,
+/// pointers or `NonZero` integers must not implement `Pod`.
+pub unsafe trait Pod: Sized {}
+
+macro_rules! impl_pod { ($($t:ty),*) => { $( unsafe impl Pod for $t {} )* } }
+impl_pod!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64, FourCC);
+unsafe impl<T: Pod, const N: usize> Pod for [T; N] {}
+
+/// The `ByteSwap` trait reverses the byte order of every field.
+///
+/// Implemented for primitive numbers and arrays. Struct layouts implement it by
+/// swapping each field, after which `ParserRead::read_struct_be` can read whole
+/// big endian headers into native structs.
+pub trait ByteSwap: Sized {
+    /// Reverse the byte order of every field.
+    fn swap_bytes(self) -> Self;
+}
+
+macro_rules! impl_byte_swap { ($($t:ty),*) => { $(
+    impl ByteSwap for $t { #[inline] fn swap_bytes(self) -> Self { <$t>::swap_bytes(self) } }
+)* } }
+impl_byte_swap!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize);
+
+impl ByteSwap for f32 { #[inline] fn swap_bytes(self) -> Self { f32::from_bits(self.to_bits().swap_bytes()) } }
+impl ByteSwap for f64 { #[inline] fn swap_bytes(self) -> Self { f64::from_bits(self.to_bits().swap_bytes()) } }
+impl ByteSwap for FourCC { #[inline] fn swap_bytes(self) -> Self { self } } // byte sequence, not an integer
+impl<T: ByteSwap, const N: usize> ByteSwap for [T; N] { #[inline] fn swap_bytes(self) -> Self { self.map(T::swap_bytes) } }
+
+/// The `FourCCExt` trait converts four character codes to and from bytes.
+///
+/// `FourCC` lives in the `fourcc` crate, so `From<[u8; 4]>` cannot be added
+/// here. Converting to bytes lets chunk types be dispatched with a `match` on
+/// byte string patterns, e.g. `match &header.fourcc.to_bytes() { b"FORM" => .. }`.
+pub trait FourCCExt {
+    /// Get the four bytes of the code.
+    fn to_bytes(&self) -> [u8; 4];
+
+    /// Construct a code from four bytes.
+    fn from_bytes(bytes: [u8; 4]) -> Self;
+
+    /// Compare the code to a byte string literal.
+    #[inline] fn matches(&self, code: &[u8; 4]) -> bool { self.to_bytes() == *code }
+}
+
+// `FourCC` is `Pod`, so it is exactly four bytes and every byte pattern is valid.
+impl FourCCExt for FourCC {
+    #[inline] fn to_bytes(&self) -> [u8; 4] { unsafe { core::mem::transmute::<FourCC, [u8; 4]>(*self) } }
+    #[inline] fn from_bytes(bytes: [u8; 4]) -> Self { unsafe { core::mem::transmute::<[u8; 4], FourCC>(bytes) } }
+}
+
+/// The `ReaderUninit` trait adds a typed read function.
+pub trait ReaderUninit<T: Sized> {
+    /// Read a plain old data type.
+    #[inline] fn read_uninit(&mut self) -> Result<T> where T: Pod
+        { unsafe { self.read_uninit_unchecked() } }
+
+    /// Read any sized type directly into uninitialised memory.
+    ///
+    /// # Safety
+    ///
+    /// The bytes read must form a valid value of `T`, see `Pod`. All
+    /// `size_of::<T>()` bytes are overwritten before the value is returned,
+    /// including padding, and a short read returns an error without ever
+    /// treating the partially filled memory as a `T`. The reader must not read
+    /// from the buffer passed to `Read::read`, use `read_uninit_zeroed` for
+    /// readers that cannot guarantee this.
+    unsafe fn read_uninit_unchecked(&mut self) -> Result<T>;
+
+    /// Read any sized type into zero initialised memory.
+    ///
+    /// # Safety
+    ///
+    /// The bytes read must form a valid value of `T`, see `Pod`. The buffer is
+    /// zeroed first, so the reader only ever sees initialised bytes.
+    unsafe fn read_uninit_zeroed(&mut self) -> Result<T>;
+}
+
+// Blanket implementation of typed read. The bytes are read into a local
+// `MaybeUninit<T>`, which is always aligned for `T`, and only ever accessed
+// through a byte pointer, so packed and misaligned layouts are sound.
+impl<R: Read, T: Sized> ReaderUninit<T> for R {
+    unsafe fn read_uninit_unchecked(&mut self) -> Result<T> {
+        let mut uninit = MaybeUninit::<T>::uninit(); // allocate memory
+        Ok( unsafe { // read directly into pointer
+            let ptr = uninit.as_mut_ptr();
+            self.read_exact(core::slice::from_raw_parts_mut(ptr as *mut u8, core::mem::size_of::<T>()))?;
+            uninit.assume_init() // confirm initialisation
+        } )
+    }
+
+    unsafe fn read_uninit_zeroed(&mut self) -> Result<T> {
+        let mut zeroed = MaybeUninit::<T>::zeroed(); // allocate zeroed memory
+        Ok( unsafe { // read into initialised bytes
+            let ptr = zeroed.as_mut_ptr();
+            self.read_exact(core::slice::from_raw_parts_mut(ptr as *mut u8, core::mem::size_of::<T>()))?;
+            zeroed.assume_init() // every byte was overwritten
+        } )
+    }
+}
+
+/// The `WriterRaw` trait adds a typed write function.
+pub trait WriterRaw<T: Sized> {
+    /// Write a plain old data type.
+    #[inline] fn write_raw(&mut self, value: &T) -> Result<()> where T: Pod
+        { unsafe { self.write_raw_unchecked(value) } }
+
+    /// Write the bytes of any sized type directly from memory.
+    ///
+    /// # Safety
+    ///
+    /// `T` must not contain padding bytes, see `Pod`.
+    unsafe fn write_raw_unchecked(&mut self, value: &T) -> Result<()>;
+}
+
+// Blanket implementation of typed write.
+impl<W: Write, T: Sized> WriterRaw<T> for W {
+    unsafe fn write_raw_unchecked(&mut self, value: &T) -> Result<()> {
+        let bytes = unsafe { // view the value as bytes
+            core::slice::from_raw_parts(value as *const T as *const u8, core::mem::size_of::<T>())
+        };
+        Ok( self.write_all(bytes)? )
+    }
+}
+
+//------------------------------------------------------------------------------
+
+/// Position counting wrapper for non-seekable readers.
+///
+/// Pipes, sockets and decompressors only implement `Read`, yet chunk formats
+/// can be parsed front to back. `StreamReader` counts every byte read and
+/// implements forward `Seek` by discarding bytes, which lets the `ParserSeek`
+/// and `ChunkParser` API work unchanged. Seeking backwards or from the end
+/// returns an `Unsupported` error.
+#[cfg(feature = "std")]
+pub struct StreamReader<R> {
+    inner: R,
+    position: u64
+}
+
+#[cfg(feature = "std")]
+impl<R: Read> StreamReader<R> {
+    /// Wrap a reader, counting from position zero.
+    pub fn new(inner: R) -> StreamReader<R> { StreamReader { inner, position: 0 } }
+
+    /// Get the number of bytes consumed so far.
+    pub fn position(&self) -> u64 { self.position }
+
+    /// Reclaim the inner reader.
+    pub fn into_inner(self) -> R { self.inner }
+}
+
+#[cfg(feature = "std")]
+impl<R: Read> Read for StreamReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        self.position += len as u64;
+        Ok( len )
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: Read> Seek for StreamReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(delta) => self.position.checked_add_signed(delta),
+            SeekFrom::End(_) => None
+        };
+        match target {
+            Some(target) if target >= self.position => { // discard bytes up to the target
+                let len = target - self.position;
+                let skipped = std::io::copy(&mut (&mut self.inner).take(len), &mut std::io::sink())?;
+                self.position += skipped;
+                if skipped < len { return Err(io::ErrorKind::UnexpectedEof.into()) }
+                Ok( self.position )
+            },
+            _ => Err(IoError::new(io::ErrorKind::Unsupported, "stream readers only seek forward"))
+        }
+    }
+
+    fn stream_position(&mut self) -> io::Result<u64> { Ok( self.position ) }
+}
+
+/// Position caching wrapper for seekable readers.
+///
+/// The parser loops query the reader position around every chunk, which costs
+/// a system call on file backed readers. `OffsetReader` counts every byte read
+/// and records the result of every seek, so `stream_position` is answered from
+/// the cached offset instead. Wrap the reader before constructing the parser.
+pub struct OffsetReader<R> {
+    inner: R,
+    position: u64
+}
+
+impl<R: Seek> OffsetReader<R> {
+    /// Wrap a reader, starting from its current position.
+    pub fn new(mut inner: R) -> io::Result<OffsetReader<R>> {
+        let position = inner.stream_position()?;
+        Ok( OffsetReader { inner, position } )
+    }
+
+    /// Get the cached reader position.
+    pub fn position(&self) -> u64 { self.position }
+
+    /// Reclaim the inner reader.
+    pub fn into_inner(self) -> R { self.inner }
+}
+
+impl<R: Read> Read for OffsetReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        self.position += len as u64;
+        Ok( len )
+    }
+}
+
+impl<R: Seek> Seek for OffsetReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.position = self.inner.seek(pos)?;
+        Ok( self.position )
+    }
+
+    fn stream_position(&mut self) -> io::Result<u64> { Ok( self.position ) }
+}
+
+/// Reader wrapper forwarding every byte read to a closure.
+///
+/// Computing a checksum or hash over chunk bodies as they are parsed avoids a
+/// second pass over large files. Seeks are passed through, so bytes skipped
+/// with `ParserSeek::skip` are not seen by the closure.
+pub struct TapReader<R, F> {
+    inner: R,
+    tap: F
+}
+
+impl<R, F: FnMut(&[u8])> TapReader<R, F> {
+    /// Wrap a reader, calling `tap` with the bytes of every read.
+    pub fn new(inner: R, tap: F) -> TapReader<R, F> { TapReader { inner, tap } }
+
+    /// Reclaim the inner reader.
+    pub fn into_inner(self) -> R { self.inner }
+}
+
+impl<R: Read, F: FnMut(&[u8])> Read for TapReader<R, F> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        (self.tap)(&buf[..len]);
+        Ok( len )
+    }
+}
+
+impl<R: Seek, F> Seek for TapReader<R, F> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> { self.inner.seek(pos) }
+
+    fn stream_position(&mut self) -> io::Result<u64> { self.inner.stream_position() }
+}
+
+//------------------------------------------------------------------------------
+
+/// Dummy constructor trait for reader types.
+///
+/// In-memory dummies never fail, while file and memory map backed dummies
+/// forward the error of the operating system instead of panicking.
+pub trait DummyReader: Sized {
+    fn dummy() -> Result<Self>;
+}
+
+// Null device path, always present on supported platforms.
+#[cfg(all(feature = "std", windows))]
+const NULL_DEVICE: &str = "NUL";
+#[cfg(all(feature = "std", not(windows)))]
+const NULL_DEVICE: &str = "/dev/null";
+
+#[cfg(feature = "std")]
+impl DummyReader for std::io::BufReader<std::fs::File> {
+    fn dummy() -> Result<std::io::BufReader<std::fs::File>>
+        { Ok( std::io::BufReader::new(std::fs::File::open(NULL_DEVICE)?) ) }
+}
+
+// Empty in-memory readers. A blanket `Cursor<T: Default>` would overlap with
+// the `Mmap` cursor below, so the common buffer types are listed explicitly.
+macro_rules! impl_dummy_cursor { ($($t:ty),*) => { $(
+    impl DummyReader for io::Cursor<$t> {
+        fn dummy() -> Result<Self> { Ok( io::Cursor::new(Default::default()) ) }
+    }
+)* } }
+impl_dummy_cursor!(Vec<u8>, Box<[u8]>, &[u8], &mut [u8], String);
+
+#[cfg(feature = "std")]
+impl DummyReader for std::io::Empty {
+    fn dummy() -> Result<std::io::Empty> { Ok( std::io::empty() ) }
+}
+
+#[cfg(feature = "mmap")]
+impl DummyReader for io::Cursor<memmap2::Mmap> {
+    fn dummy() -> Result<io::Cursor<memmap2::Mmap>> {
+        let map = memmap2::MmapOptions::new().map_anon()?.make_read_only()?;
+        Ok( io::Cursor::new(map) )
+    }
+}
+
+//------------------------------------------------------------------------------
+
+/// The `HeaderParser` trait defines unique header parsing logic.
+pub trait HeaderParser<H> {
+    fn header(&mut self) -> Result<H>;
+
+    /// Check a header before its chunk body is parsed.
+    ///
+    /// Called by the parser loops after every `header`. The default accepts
+    /// every header, strict formats override it to centralise sanity checks.
+    fn validate_header(&mut self, _header: &H) -> Result<()> { Ok(()) }
+
+    /// Expose a header as a `ChunkHeader`, if its type implements it.
+    ///
+    /// The generic parser loops can't require `ChunkHeader`, so they use this
+    /// hook to name the chunk type in errors. Parsers whose header implements
+    /// `ChunkHeader`, e.g. through `impl_chunk_header!`, return `Some(header)`.
+    fn as_chunk_header<'a>(&self, _header: &'a H) -> Option<&'a dyn ChunkHeader> { None }
+}
+
+/// The `ChunkHeader` trait exposes the type and declared body size of a header.
+pub trait ChunkHeader {
+    /// Declared size of the chunk body in bytes.
+    fn size(&self) -> u64;
+
+    /// Chunk type.
+    fn fourcc(&self) -> FourCC;
+}
+
+/// Implement `ChunkHeader` for a header struct by naming its fields.
+///
+/// For example `impl_chunk_header!(IFFHeader, fourcc: typeid, size: length)`.
+/// The size field may be any unsigned integer up to `u64` and is widened
+/// losslessly, so headers get the header sized loops, iteration and indexing
+/// without a hand written impl.
+#[macro_export]
+macro_rules! impl_chunk_header { ($t:ty, fourcc: $fourcc:ident, size: $size:ident) => {
+    impl $crate::ChunkHeader for $t {
+        fn size(&self) -> u64 { u64::from(self.$size) }
+        fn fourcc(&self) -> $crate::FourCC { self.$fourcc }
+    }
+} }
+
+/// The canonical 8 byte chunk header.
+///
+/// Most formats derived from IFF start each chunk with a four character code
+/// followed by a 32-bit body size. Parsers with this layout implement
+/// `HeaderParser<StandardHeader>` with `read_standard_header()`.
+#[derive(Debug, Clone, Copy)]
+pub struct StandardHeader {
+    pub fourcc: FourCC, // Chunk type.
+    pub size: u32 // Chunk body size.
+}
+
+impl_chunk_header!(StandardHeader, fourcc: fourcc, size: size);
+
+impl StandardHeader {
+    /// Bytes taken by the header itself, the code and the size field.
+    pub const SIZE: u64 = (core::mem::size_of::<FourCC>() + core::mem::size_of::<u32>()) as u64;
+}
+
+/// What a declared chunk size covers.
+///
+/// Formats like QuickTime count the header in the chunk size. Converting to
+/// the body size when the header is read keeps every parser loop working on
+/// body sizes, see `ParserRead::read_standard_header_with`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SizeConvention {
+    Body, // Size covers the chunk body only.
+    Chunk // Size covers the header and the body.
+}
+
+impl SizeConvention {
+    /// Convert a declared size to the body size of a `header_size` byte header.
+    ///
+    /// A chunk size smaller than its own header returns `Error::ParseError`.
+    pub fn body_size(self, declared: u64, header_size: u64) -> Result<u64> {
+        match self {
+            SizeConvention::Body => Ok( declared ),
+            SizeConvention::Chunk => declared.checked_sub(header_size).ok_or(Error::ParseError)
+        }
+    }
+}
+
+/// Signature for parser closures.
+pub type ParserFn<P,H> = fn(parser: &mut P, header: &H) -> Result<u64>;
+
+/// Signature for parser closures when the header declares the chunk size.
+pub type ParserAutoFn<P,H> = fn(parser: &mut P, header: &H) -> Result<()>;
+
+/// Signature for parser closures receiving the chunk context.
+pub type ParserContextFn<P,H> = fn(parser: &mut P, header: &H, context: &ChunkContext) -> Result<u64>;
+
+/// Signature for parser closures that can stop the loop early.
+///
+/// Closures return `ControlFlow::Continue(size)` to carry on with the next
+/// chunk, or `ControlFlow::Break(())` to stop parsing without an error.
+pub type ParserFlowFn<P,H> = fn(parser: &mut P, header: &H) -> Result<ControlFlow<(), u64>>;
+
+/// The `ChunkParser` trait defines the inner parser loop.
+///
+/// Every loop variant runs on `chunk_loop`, differing only in the step called
+/// for each chunk. The top level `parse` variants move the reader back to its
+/// position before the call on error, so the same reader can be handed to a
+/// fallback parser.
+pub trait ChunkParser<R: Read + Seek>: ParserRead<R> + ParserDepth {
+    /// Chunk alignment applied by the parser loops, defaults to 1.
+    ///
+    /// Formats padding every chunk body to an even length return 2, so `parse`
+    /// and `subchunks` step over the pad bytes without a padded variant. Must
+    /// be non-zero.
+    fn chunk_alignment(&self) -> u64 { 1 }
+
+    /// Receive the reader size found by the top level parse variants.
+    ///
+    /// The default ignores it. Parsers implementing `ParserTotalSize` override
+    /// it with `*self.inner_total_size() = Some(total_size)`, so closures get
+    /// the size without another seek to the end.
+    fn record_total_size(&mut self, _total_size: u64) {}
+
+    /// Charge a chunk covering `bytes` bytes, header included, to the budgets.
+    ///
+    /// Called by every parser loop before the body is parsed, or after it
+    /// where the header doesn't declare the body size. The default does
+    /// nothing. Parsers implementing `ParserStats` override it with
+    /// `self.charge_chunk(context, bytes)`.
+    fn charge(&mut self, _context: &ChunkContext, _bytes: u64) -> Result<()> { Ok(()) }
+
+    /// Internal parser loop shared by every loop variant.
+    ///
+    /// Each header is read and validated, then handed to `step` with the reader
+    /// at the start of the body. The step returns the number of body bytes it
+    /// consumed, or `ControlFlow::Break` to stop the loop early. `describe`
+    /// gives the type and declared body size of a header where known, for
+    /// error context and tracing. A declared size running past `total_size`
+    /// returns `Error::ParseError` before the step is called, otherwise the
+    /// chunk is passed to `charge`. Headers without a declared size get the
+    /// same check on the size returned by the step. Bodies are padded
+    /// to a multiple of `align` bytes, which must be non-zero, and the pad after
+    /// the final chunk may be omitted.
+    ///
+    /// The body starts wherever `header` leaves the reader, so headers of any
+    /// width work without declaring it.
+    fn chunk_loop<H>(
+        &mut self, total_size: u64, align: u64,
+        describe: impl Fn(&Self, &H) -> Option<(FourCC, u64)>,
+        mut step: impl FnMut(&mut Self, H, &ChunkContext) -> Result<ControlFlow<(), u64>>
+    ) -> Result<ControlFlow<()>> where Self: HeaderParser<H> {
+        loop {
+            let offset = self.reader().stream_position()?;
+            let mut fourcc = None;
+            let pos = (|| {
+                let context = ChunkContext { offset, depth: self.depth(), total_size };
+                let header = self.header()?;
+                self.validate_header(&header)?;
+                let chunk = describe(self, &header);
+                fourcc = chunk.map(|(fourcc, _)| fourcc);
+                trace_span!("chunk", offset, depth = context.depth, fourcc = ?fourcc, size = ?chunk.map(|(_, size)| size));
+                let start = self.reader().stream_position()?;
+                if let Some((_, size)) = chunk { // check the declared size before the body is touched
+                    let end = start.checked_add(size).ok_or(Error::SizeOverflow)?;
+                    if end > total_size { return Err(Error::ParseError) } // chunk overruns its parent
+                    self.charge(&context, end - offset)?;
+                }
+                let size = match step(self, header, &context)? { // the step is responsible for parsing the size
+                    ControlFlow::Continue(size) => size,
+                    ControlFlow::Break(()) => return Ok( None )
+                };
+                let end = start.checked_add(size).ok_or(Error::SizeOverflow)?;
+                if end > total_size { return Err(Error::ParseError) } // chunk overran its parent
+                let pos = self.reader().stream_position()?;
+                if chunk.is_none() { self.charge(&context, pos.saturating_sub(offset))? } // body size only known now
+                if pos == total_size { return Ok( Some(pos) ) } // function consumed chunk
+                else if pos != end { return Err(Error::ChunkSizeMismatch { expected_end: end, actual_pos: pos }) } // function made a mistake
+                let padded = padded_end(start, size, align)?;
+                if padded == end { return Ok( Some(pos) ) }
+                Ok( Some(self.reader().seek(SeekFrom::Start(padded.min(total_size)))?) ) // skip pad bytes
+            })().map_err(|e| e.in_chunk(fourcc, offset))?;
+            match pos {
+                None => break Ok( ControlFlow::Break(()) ), // function stopped the loop
+                Some(pos) if pos == total_size => break Ok( ControlFlow::Continue(()) ), // function consumed chunk
+                Some(pos) if pos <= offset => break Err(Error::ParseError.in_chunk(fourcc, offset)), // no progress
+                _ => ()
+            }
+        }
+    }
+
+    /// Internal parser loop.
+    fn parse_loop<H>(&mut self, f: ParserFn<Self,H>, total_size: u64) -> Result<()> where Self: HeaderParser<H>
+        { self.parse_loop_padded(f, total_size, self.chunk_alignment()) }
+
+    /// Internal parser loop skipping pad bytes after each chunk.
+    ///
+    /// Formats like RIFF pad chunk bodies to a multiple of `align` bytes without
+    /// counting the pad in the chunk size. The pad after the final chunk may be
+    /// omitted. `align` must be non-zero.
+    fn parse_loop_padded<H>(&mut self, f: ParserFn<Self,H>, total_size: u64, align: u64) -> Result<()> where Self: HeaderParser<H> {
+        self.chunk_loop(total_size, align, hooked, |parser, header, _| Ok( ControlFlow::Continue(f(parser, &header)?) ))
+            .map(|_| ())
+    }
+
+    /// Build an `Error::UnknownChunk` for `header` at the current position.
+    ///
+    /// Meant for the default arm of a closure matching on the chunk type, e.g.
+    /// `_ => Err(parser.unknown_chunk(header))`.
+    fn unknown_chunk<H: ChunkHeader>(&mut self, header: &H) -> Error {
+        match self.reader().stream_position() {
+            Ok(offset) => Error::UnknownChunk { fourcc: header.fourcc(), offset },
+            Err(e) => e.into()
+        }
+    }
+
+    /// Parse top level chunk(s) from the reader.
+    #[inline]
+    fn parse<H>(&mut self, f: ParserFn<Self,H>) -> Result<()> where Self: HeaderParser<H>
+        { parse_whole(self, |parser, total_size| parser.parse_loop(f, total_size)) }
+
+    /// Parse the chunks in the region `[start, start + size)` of the reader.
+    ///
+    /// Chunk streams embedded part way through a larger container can be parsed
+    /// without treating the whole reader as one stream. On error the reader is
+    /// moved back to its position before the call.
+    fn parse_from<H>(&mut self, f: ParserFn<Self,H>, start: u64, size: u64) -> Result<()> where Self: HeaderParser<H> {
+        let initial = self.reader().stream_position()?;
+        let res = (|| {
+            let end = start.checked_add(size).ok_or(Error::SizeOverflow)?;
+            self.reader().seek(SeekFrom::Start(start))?;
+            self.parse_loop(f, end)
+        })();
+        if res.is_err() { self.reader().seek(SeekFrom::Start(initial))?; }
+        res
+    }
+
+    /// Parse top level chunk(s), checking the first header against the reader size.
+    ///
+    /// Formats like IFF and RIFF wrap the whole file in a single chunk. The size
+    /// declared by the first header must match the remaining bytes, allowing one
+    /// pad byte after an odd size, or `Error::SizeMismatch` is returned.
+    fn parse_checked<H: ChunkHeader>(&mut self, f: ParserFn<Self,H>) -> Result<()> where Self: HeaderParser<H> {
+        parse_whole(self, |parser, total_size| {
+            let header = parser.header().map_err(|e| e.in_chunk(None, 0))?;
+            let (declared, actual) = (header.size(), total_size - parser.reader().stream_position()?);
+            if actual != declared && (declared % 2 == 0 || actual != declared + 1) {
+                return Err(Error::SizeMismatch { declared, actual }.in_chunk(Some(header.fourcc()), 0))
+            }
+            parser.reader().seek(SeekFrom::Start(0))?;
+            parser.parse_loop(f, total_size)
+        })
+    }
+
+    /// Parse nested subchunks within the main parse routine.
+    #[inline]
+    fn subchunks<H>(&mut self, f: ParserFn<Self,H>, total_size: u64) -> Result<()> where Self: HeaderParser<H>
+        { self.subchunks_padded(f, total_size, self.chunk_alignment()) }
+
+    /// Internal loop over nested subchunks shared by every `subchunks` variant.
+    ///
+    /// Runs `chunk_loop` over the next `total_size` bytes one level deeper,
+    /// popping the depth again however the loop ends.
+    fn subchunks_with<H>(
+        &mut self, total_size: u64, align: u64,
+        describe: impl Fn(&Self, &H) -> Option<(FourCC, u64)>,
+        step: impl FnMut(&mut Self, H, &ChunkContext) -> Result<ControlFlow<(), u64>>
+    ) -> Result<ControlFlow<()>> where Self: HeaderParser<H> {
+        self.try_push()?;
+        trace_span!("subchunks", depth = self.depth(), size = total_size);
+        let res = (|| {
+            let pos = self.reader().stream_position()?;
+            self.chunk_loop(pos.checked_add(total_size).ok_or(Error::SizeOverflow)?, align, describe, step)
+        })();
+        self.pop();
+        res
+    }
+
+    /// Parse top level chunk(s) padded to a multiple of `align` bytes.
+    #[inline]
+    fn parse_padded<H>(&mut self, f: ParserFn<Self,H>, align: u64) -> Result<()> where Self: HeaderParser<H>
+        { parse_whole(self, |parser, total_size| parser.parse_loop_padded(f, total_size, align)) }
+
+    /// Parse nested subchunks padded to a multiple of `align` bytes.
+    #[inline]
+    fn subchunks_padded<H>(&mut self, f: ParserFn<Self,H>, total_size: u64, align: u64) -> Result<()> where Self: HeaderParser<H> {
+        self.subchunks_with(total_size, align, hooked, |parser, header, _| Ok( ControlFlow::Continue(f(parser, &header)?) ))
+            .map(|_| ())
+    }
+
+    /// Parse top level chunk(s) using the parser alignment.
+    #[inline]
+    fn parse_aligned<H>(&mut self, f: ParserFn<Self,H>) -> Result<()> where Self: HeaderParser<H> + ParserAlign<R> {
+        let align = self.alignment();
+        self.parse_padded(f, align)
+    }
+
+    /// Parse nested subchunks using the parser alignment.
+    #[inline]
+    fn subchunks_aligned<H>(&mut self, f: ParserFn<Self,H>, total_size: u64) -> Result<()> where Self: HeaderParser<H> + ParserAlign<R> {
+        let align = self.alignment();
+        self.subchunks_padded(f, total_size, align)
+    }
+
+    /// Internal parser loop passing the chunk context to `f`.
+    fn parse_loop_context<H>(&mut self, f: ParserContextFn<Self,H>, total_size: u64) -> Result<()> where Self: HeaderParser<H> {
+        self.chunk_loop(total_size, self.chunk_alignment(), hooked, |parser, header, context| Ok( ControlFlow::Continue(f(parser, &header, context)?) ))
+            .map(|_| ())
+    }
+
+    /// Parse top level chunk(s), passing the chunk context to `f`.
+    #[inline]
+    fn parse_with_context<H>(&mut self, f: ParserContextFn<Self,H>) -> Result<()> where Self: HeaderParser<H>
+        { parse_whole(self, |parser, total_size| parser.parse_loop_context(f, total_size)) }
+
+    /// Parse nested subchunks, passing the chunk context to `f`.
+    #[inline]
+    fn subchunks_with_context<H>(&mut self, f: ParserContextFn<Self,H>, total_size: u64) -> Result<()> where Self: HeaderParser<H> {
+        self.subchunks_with(total_size, self.chunk_alignment(), hooked, |parser, header, context| Ok( ControlFlow::Continue(f(parser, &header, context)?) ))
+            .map(|_| ())
+    }
+
+    /// Internal parser loop that stops when `f` breaks.
+    ///
+    /// Returns `ControlFlow::Break` if the loop was stopped early, which nested
+    /// closures can forward to stop the enclosing loop as well.
+    fn parse_loop_flow<H>(&mut self, f: ParserFlowFn<Self,H>, total_size: u64) -> Result<ControlFlow<()>> where Self: HeaderParser<H>
+        { self.chunk_loop(total_size, self.chunk_alignment(), hooked, |parser, header, _| f(parser, &header)) }
+
+    /// Parse top level chunk(s), stopping early when `f` breaks.
+    #[inline]
+    fn parse_flow<H>(&mut self, f: ParserFlowFn<Self,H>) -> Result<()> where Self: HeaderParser<H>
+        { parse_whole(self, |parser, total_size| parser.parse_loop_flow(f, total_size).map(|_| ())) }
+
+    /// Parse nested subchunks, stopping early when `f` breaks.
+    #[inline]
+    fn subchunks_flow<H>(&mut self, f: ParserFlowFn<Self,H>, total_size: u64) -> Result<ControlFlow<()>> where Self: HeaderParser<H>
+        { self.subchunks_with(total_size, self.chunk_alignment(), hooked, |parser, header, _| f(parser, &header)) }
+
+    /// Internal parser loop calling the handler registered for each chunk type.
+    fn parse_loop_dispatch<H: ChunkHeader>(&mut self, dispatcher: &ChunkDispatcher<Self,H>, total_size: u64) -> Result<()> where Self: HeaderParser<H> + Sized {
+        self.chunk_loop(total_size, self.chunk_alignment(), declared, |parser, header, _| dispatch_chunk(parser, dispatcher, &header))
+            .map(|_| ())
+    }
+
+    /// Parse top level chunk(s) with the handlers of a dispatch table.
+    #[inline]
+    fn parse_dispatch<H: ChunkHeader>(&mut self, dispatcher: &ChunkDispatcher<Self,H>) -> Result<()> where Self: HeaderParser<H> + Sized
+        { parse_whole(self, |parser, total_size| parser.parse_loop_dispatch(dispatcher, total_size)) }
+
+    /// Parse nested subchunks with the handlers of a dispatch table.
+    #[inline]
+    fn subchunks_dispatch<H: ChunkHeader>(&mut self, dispatcher: &ChunkDispatcher<Self,H>, total_size: u64) -> Result<()> where Self: HeaderParser<H> + Sized {
+        self.subchunks_with(total_size, self.chunk_alignment(), declared, |parser, header, _| dispatch_chunk(parser, dispatcher, &header))
+            .map(|_| ())
+    }
+
+    /// Internal parser loop accumulating chunk statistics.
+    ///
+    /// Statistics are collected by `charge` in every loop, this variant only
+    /// requires the parser to keep them.
+    #[inline]
+    fn parse_loop_counted<H>(&mut self, f: ParserFn<Self,H>, total_size: u64) -> Result<()> where Self: HeaderParser<H> + ParserStats
+        { self.parse_loop(f, total_size) }
+
+    /// Parse top level chunk(s), accumulating chunk statistics.
+    #[inline]
+    fn parse_counted<H>(&mut self, f: ParserFn<Self,H>) -> Result<()> where Self: HeaderParser<H> + ParserStats
+        { parse_whole(self, |parser, total_size| parser.parse_loop_counted(f, total_size)) }
+
+    /// Parse nested subchunks, accumulating chunk statistics.
+    #[inline]
+    fn subchunks_counted<H>(&mut self, f: ParserFn<Self,H>, total_size: u64) -> Result<()> where Self: HeaderParser<H> + ParserStats
+        { self.subchunks(f, total_size) }
+
+    /// Internal parser loop recording the end of each chunk body.
+    ///
+    /// The chunk end of the enclosing chunk is restored when the loop returns.
+    fn parse_loop_tracked<H: ChunkHeader>(&mut self, f: ParserFn<Self,H>, total_size: u64) -> Result<()> where Self: HeaderParser<H> + ParserChunk<R> {
+        let parent_end = self.chunk_end();
+        let res = self.chunk_loop(total_size, self.chunk_alignment(), declared, |parser, header, _| tracked_chunk(parser, f, &header));
+        *self.inner_chunk_end() = parent_end;
+        res.map(|_| ())
+    }
+
+    /// Parse top level chunk(s), recording the end of each chunk body.
+    #[inline]
+    fn parse_tracked<H: ChunkHeader>(&mut self, f: ParserFn<Self,H>) -> Result<()> where Self: HeaderParser<H> + ParserChunk<R>
+        { parse_whole(self, |parser, total_size| parser.parse_loop_tracked(f, total_size)) }
+
+    /// Parse nested subchunks, recording the end of each chunk body.
+    #[inline]
+    fn subchunks_tracked<H: ChunkHeader>(&mut self, f: ParserFn<Self,H>, total_size: u64) -> Result<()> where Self: HeaderParser<H> + ParserChunk<R> {
+        let parent_end = self.chunk_end();
+        let res = self.subchunks_with(total_size, self.chunk_alignment(), declared, |parser, header, _| tracked_chunk(parser, f, &header));
+        *self.inner_chunk_end() = parent_end;
+        res.map(|_| ())
+    }
+
+    /// Internal parser loop deriving chunk boundaries from the header.
+    ///
+    /// After `f` returns the reader is moved to the end of the body declared by
+    /// the header, so `f` only needs to read the fields it cares about. Reading
+    /// past the declared body is an `Error::ChunkOverrun`.
+    fn parse_loop_auto<H: ChunkHeader>(&mut self, f: ParserAutoFn<Self,H>, total_size: u64) -> Result<()> where Self: HeaderParser<H> {
+        self.chunk_loop(total_size, self.chunk_alignment(), declared, |parser, header, _| Ok( ControlFlow::Continue(auto_chunk(parser, f, &header)?) ))
+            .map(|_| ())
+    }
+
+    /// Parse top level chunk(s), deriving chunk boundaries from the header.
+    #[inline]
+    fn parse_auto<H: ChunkHeader>(&mut self, f: ParserAutoFn<Self,H>) -> Result<()> where Self: HeaderParser<H>
+        { parse_whole(self, |parser, total_size| parser.parse_loop_auto(f, total_size)) }
+
+    /// Parse a file of concatenated top level chunks, returning their count.
+    ///
+    /// Each top level chunk is an independent document, `f` is called once per
+    /// document and the reader is then moved to the end of the body declared by
+    /// the header. Unlike `parse_auto` an empty reader holds zero documents.
+    fn parse_documents<H: ChunkHeader>(&mut self, f: ParserAutoFn<Self,H>) -> Result<usize> where Self: HeaderParser<H> {
+        parse_whole(self, |parser, total_size| {
+            let mut count = 0;
+            if total_size == 0 { return Ok( count ) } // no documents
+            parser.chunk_loop(total_size, parser.chunk_alignment(), declared, |parser, header, _| {
+                trace_span!("document", index = count);
+                let size = auto_chunk(parser, f, &header)?;
+                count += 1;
+                Ok( ControlFlow::Continue(size) )
+            }).map(|_| count)
+        })
+    }
+
+    /// Parse nested subchunks, deriving chunk boundaries from the header.
+    #[inline]
+    fn subchunks_auto<H: ChunkHeader>(&mut self, f: ParserAutoFn<Self,H>, total_size: u64) -> Result<()> where Self: HeaderParser<H> {
+        self.subchunks_with(total_size, self.chunk_alignment(), declared, |parser, header, _| Ok( ControlFlow::Continue(auto_chunk(parser, f, &header)?) ))
+            .map(|_| ())
+    }
+
+    /// Internal tree builder for the chunks between the reader position and `end`.
+    fn parse_tree_loop<H: ChunkHeader>(&mut self, container: fn(&H) -> bool, end: u64) -> Result<Vec<ChunkNode<H>>> where Self: HeaderParser<H> {
+        let mut nodes = Vec::new();
+        if self.reader().stream_position()? == end { return Ok( nodes ) } // empty container
+        self.chunk_loop(end, self.chunk_alignment(), declared, |parser, header, _| {
+            let start = parser.reader().stream_position()?;
+            let body_end = start.checked_add(header.size()).ok_or(Error::SizeOverflow)?;
+            let children = if container(&header) {
+                parser.try_push()?;
+                match parser.parse_tree_loop(container, body_end) {
+                    res => { parser.pop(); res? }
+                }
+            } else { Vec::new() };
+            parser.reader().seek(SeekFrom::Start(body_end))?;
+            nodes.push(ChunkNode { header, offset: start, children });
+            Ok( ControlFlow::Continue(body_end - start) )
+        }).map(|_| nodes)
+    }
+
+    /// Collect the chunk tree into an owned structure.
+    ///
+    /// Chunks for which `container` returns true are recursed into, with their
+    /// subchunks starting at the beginning of the body.
+    #[inline]
+    fn parse_tree<H: ChunkHeader>(&mut self, container: fn(&H) -> bool) -> Result<Vec<ChunkNode<H>>> where Self: HeaderParser<H>
+        { parse_whole(self, |parser, total_size| parser.parse_tree_loop(container, total_size)) }
+
+    /// Write an indented tree of every chunk, e.g. for a dump tool.
+    ///
+    /// Each line holds the four character code, body offset and body size,
+    /// indented by two spaces per nesting level. Chunks for which `container`
+    /// returns true are descended into, as in `parse_tree`.
+    fn debug_dump<H: ChunkHeader>(&mut self, container: fn(&H) -> bool, out: &mut impl core::fmt::Write) -> Result<()> where Self: HeaderParser<H> {
+        fn dump<H: ChunkHeader>(nodes: &[ChunkNode<H>], depth: usize, out: &mut impl core::fmt::Write) -> core::fmt::Result {
+            for node in nodes {
+                writeln!(out, "{:indent$}{} offset {:#x} size {}", "", node.header.fourcc(), node.offset, node.header.size(), indent = depth * 2)?;
+                dump(&node.children, depth + 1, out)?;
+            }
+            Ok(())
+        }
+        let tree = self.parse_tree(container)?;
+        dump(&tree, 0, out).map_err(|_| Error::IoError(IoError::other("formatter error")))
+    }
+
+    /// Build a flat index of `(body offset, header)` pairs in file order.
+    ///
+    /// Chunks for which `container` returns true are descended into, pass
+    /// `|_| false` to index top level chunks only. The reader is returned to
+    /// the start afterwards, ready to seek to any indexed body.
+    fn index<H: ChunkHeader>(&mut self, container: fn(&H) -> bool) -> Result<Vec<(u64, H)>> where Self: HeaderParser<H> {
+        let mut stack: Vec<ChunkNode<H>> = self.parse_tree(container)?.into_iter().rev().collect();
+        self.reader().seek(SeekFrom::Start(0))?;
+        let mut index = Vec::new();
+        while let Some(ChunkNode { header, offset, children }) = stack.pop() {
+            stack.extend(children.into_iter().rev()); // visit subchunks before siblings
+            index.push((offset, header));
+        }
+        Ok( index )
+    }
+
+    /// Iterate over top level chunk headers.
+    ///
+    /// Each header is yielded with the reader positioned at the start of its
+    /// body, and the body is skipped when the next header is requested.
+    #[inline]
+    fn chunks<H: ChunkHeader>(&mut self) -> ChunkIter<'_, Self, H, R> where Self: HeaderParser<H> + Sized {
+        ChunkIter { parser: self, next: 0, total_size: None, done: false, marker: core::marker::PhantomData }
+    }
+
+    /// Skip chunks from the current position until `pred` matches a header.
+    ///
+    /// Returns the matching header with the reader positioned at its body, or
+    /// `None` when the reader ends on a chunk boundary first. Bodies and their
+    /// pad bytes are passed over with forward seeks, so this also works on
+    /// `StreamReader`.
+    fn skip_to<H: ChunkHeader>(&mut self, pred: impl Fn(&H) -> bool) -> Result<Option<H>> where Self: HeaderParser<H> {
+        loop {
+            let offset = self.reader().stream_position()?;
+            let header = match self.header() {
+                Ok(header) => header,
+                Err(Error::UnexpectedEof) if self.reader().stream_position()? <= offset => return Ok(None), // nothing past the final pad
+                Err(e) => return Err(e.in_chunk(None, offset))
+            };
+            if pred(&header) { return Ok( Some(header) ) }
+            let align = self.chunk_alignment();
+            if !skip_padded(self, header.size(), align).map_err(|e| e.in_chunk(Some(header.fourcc()), offset))? {
+                return Ok(None) // reader ended in the final pad
+            }
+        }
+    }
+
+    /// Find the first top level chunk with a matching four character code.
+    ///
+    /// Leaves the reader positioned at the body of the matching chunk.
+    #[inline]
+    fn find<H: ChunkHeader>(&mut self, fourcc: &[u8; 4]) -> Result<Option<H>> where Self: HeaderParser<H> {
+        self.reader().seek(SeekFrom::Start(0))?;
+        self.skip_to(|header: &H| header.fourcc() == *fourcc)
+    }
+
+    /// Find every top level chunk with a matching four character code.
+    fn find_all<H: ChunkHeader>(&mut self, fourcc: &[u8; 4]) -> Result<Vec<H>> where Self: HeaderParser<H> {
+        self.reader().seek(SeekFrom::Start(0))?;
+        let mut found = Vec::new();
+        while let Some(header) = self.skip_to(|header: &H| header.fourcc() == *fourcc)? {
+            let more = skip_padded(self, header.size(), self.chunk_alignment())?; // skip the matching body
+            found.push(header);
+            if !more { break }
+        }
+        Ok( found )
+    }
+
+    /// Parse top level chunk(s), skipping chunks rejected as unknown.
+    ///
+    /// When the parser function returns `Error::UnknownChunk` the reader is moved
+    /// to the end of the body declared by the header and parsing continues. The
+    /// headers of all skipped chunks are returned.
+    fn parse_lenient<H: ChunkHeader>(&mut self, f: ParserFn<Self,H>) -> Result<Vec<H>> where Self: HeaderParser<H> {
+        parse_whole(self, |parser, total_size| {
+            let mut skipped = Vec::new();
+            parser.chunk_loop(total_size, parser.chunk_alignment(), declared, |parser, header, _| {
+                let start = parser.reader().stream_position()?;
+                let chunk_end = start.checked_add(header.size()).ok_or(Error::SizeOverflow)?;
+                let size = match f(parser, &header) {
+                    Err(Error::UnknownChunk { .. }) => { // skip the declared body
+                        #[cfg(feature = "tracing")]
+                        tracing::debug!(offset = start, fourcc = ?header.fourcc(), "skipped unknown chunk");
+                        parser.reader().seek(SeekFrom::Start(chunk_end))?;
+                        skipped.push(header);
+                        chunk_end - start
+                    },
+                    res => res?
+                };
+                Ok( ControlFlow::Continue(size) )
+            }).map(|_| skipped)
+        })
+    }
+}
+
+// Run `f` over the whole reader from the start, moving the reader back to its
+// position before the call on error. Shared by the top level parse variants.
+fn parse_whole<R, P, T>(parser: &mut P, f: impl FnOnce(&mut P, u64) -> Result<T>) -> Result<T>
+where R: Read + Seek, P: ChunkParser<R> + ?Sized {
+    let initial = parser.reader().stream_position()?;
+    let res = (|| {
+        let total_size = parser.reader().seek(SeekFrom::End(0))?;
+        parser.record_total_size(total_size);
+        parser.reader().seek(SeekFrom::Start(0))?;
+        f(parser, total_size)
+    })();
+    if res.is_err() { parser.reader().seek(SeekFrom::Start(initial))?; }
+    res
+}
+
+// Describe a header through `HeaderParser::as_chunk_header`, for the loops
+// accepting any header type.
+fn hooked<P: HeaderParser<H> + ?Sized, H>(parser: &P, header: &H) -> Option<(FourCC, u64)>
+    { parser.as_chunk_header(header).map(|header| (header.fourcc(), header.size())) }
+
+// Describe a header implementing `ChunkHeader`.
+fn declared<P: ?Sized, H: ChunkHeader>(_parser: &P, header: &H) -> Option<(FourCC, u64)>
+    { Some((header.fourcc(), header.size())) }
+
+// End of a chunk body starting at `start`, padded to a multiple of `align`.
+fn padded_end(start: u64, size: u64, align: u64) -> Result<u64>
+    { size.checked_next_multiple_of(align).and_then(|size| start.checked_add(size)).ok_or(Error::SizeOverflow) }
+
+// Skip a chunk body and its pad bytes, returning false when the reader ends
+// in the pad after the final chunk.
+fn skip_padded<R: Seek, P: ParserReader<R> + ?Sized>(parser: &mut P, size: u64, align: u64) -> Result<bool> {
+    let pad = size.checked_next_multiple_of(align).ok_or(Error::SizeOverflow)? - size;
+    skip_body(parser, size)?;
+    match skip_body(parser, pad) {
+        Err(Error::UnexpectedEof) => Ok( false ), // pad omitted after the final chunk
+        res => res.map(|_| true)
+    }
+}
+
+// Skip a declared chunk body, returning its size.
+fn skip_body<R: Seek, P: ParserReader<R> + ?Sized>(parser: &mut P, size: u64) -> Result<u64> {
+    let delta = i64::try_from(size).map_err(|_| Error::SizeOverflow)?;
+    parser.reader().seek(SeekFrom::Current(delta))?;
+    Ok( size )
+}
+
+// Call the handler registered for a chunk type, skipping or rejecting chunks
+// without one. Shared by the dispatch loops.
+fn dispatch_chunk<R: Read + Seek, P: ChunkParser<R>, H: ChunkHeader>(parser: &mut P, dispatcher: &ChunkDispatcher<P,H>, header: &H) -> Result<ControlFlow<(), u64>> {
+    let size = match dispatcher.handler(header.fourcc()) {
+        Some(f) => f(parser, header)?, // the parser function is responsible for parsing the size
+        None if dispatcher.skip_unknown => skip_body(parser, header.size())?, // skip the declared body
+        None => return Err(parser.unknown_chunk(header))
+    };
+    Ok( ControlFlow::Continue(size) )
+}
+
+// Record the end of a chunk body before calling `f`. Shared by the tracked
+// loops.
+fn tracked_chunk<R: Seek, P: ParserChunk<R> + ?Sized, H: ChunkHeader>(parser: &mut P, f: ParserFn<P,H>, header: &H) -> Result<ControlFlow<(), u64>> {
+    let start = parser.reader().stream_position()?;
+    *parser.inner_chunk_end() = start.checked_add(header.size()).ok_or(Error::SizeOverflow)?;
+    Ok( ControlFlow::Continue(f(parser, header)?) ) // the parser function is responsible for parsing the size
+}
+
+// Call `f` for a chunk whose boundaries come from the header, then move the
+// reader to the end of the declared body. Shared by the auto loops.
+fn auto_chunk<R: Seek, P: ParserReader<R> + ?Sized, H: ChunkHeader>(parser: &mut P, f: ParserAutoFn<P,H>, header: &H) -> Result<u64> {
+    let start = parser.reader().stream_position()?;
+    let end = start.checked_add(header.size()).ok_or(Error::SizeOverflow)?;
+    f(parser, header)?;
+    let pos = parser.reader().stream_position()?;
+    if pos > end { return Err(Error::ChunkOverrun) } // function read too much
+    else if pos < end { parser.reader().seek(SeekFrom::Start(end))?; } // skip the remainder
+    Ok( header.size() )
+}
+
+/// Table of chunk handlers keyed by four character code.
+///
+/// `ChunkParser::parse_dispatch` looks up the handler for each chunk type,
+/// replacing a growing `match` on the header. Chunks without a handler are
+/// rejected with `Error::UnknownChunk`, or skipped after `skip_unknown`.
+pub struct ChunkDispatcher<P, H> {
+    handlers: Vec<([u8; 4], ParserFn<P,H>)>,
+    skip_unknown: bool
+}
+
+impl<P, H> ChunkDispatcher<P, H> {
+    /// Create an empty dispatch table.
+    pub fn new() -> ChunkDispatcher<P, H> { ChunkDispatcher { handlers: Vec::new(), skip_unknown: false } }
+
+    /// Register the handler for a chunk type, replacing any previous handler.
+    pub fn on(&mut self, fourcc: &[u8; 4], f: ParserFn<P,H>) -> &mut Self {
+        match self.handlers.iter_mut().find(|(key, _)| key == fourcc) {
+            Some(entry) => entry.1 = f,
+            None => self.handlers.push((*fourcc, f))
+        }
+        self
+    }
+
+    /// Skip chunks without a handler instead of returning an error.
+    pub fn skip_unknown(&mut self, skip: bool) -> &mut Self {
+        self.skip_unknown = skip;
+        self
+    }
+
+    /// Look up the handler for a chunk type.
+    pub fn handler(&self, fourcc: FourCC) -> Option<ParserFn<P,H>> {
+        self.handlers.iter().find(|(key, _)| fourcc == *key).map(|(_, f)| *f)
+    }
+}
+
+impl<P, H> Default for ChunkDispatcher<P, H> {
+    fn default() -> Self { ChunkDispatcher::new() }
+}
+
+/// Position of a chunk within the file, see `ChunkParser::parse_with_context`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkContext {
+    pub offset: u64, // Position of the chunk header.
+    pub depth: u8, // Nesting depth of the chunk.
+    pub total_size: u64 // End position of the enclosing chunk or reader.
+}
+
+/// Owned chunk tree node, see `ChunkParser::parse_tree`.
+#[derive(Debug, Clone)]
+pub struct ChunkNode<H> {
+    pub header: H, // Chunk header.
+    pub offset: u64, // Position of the chunk body.
+    pub children: Vec<ChunkNode<H>> // Subchunks of container chunks.
+}
+
+/// Iterator over top level chunk headers, see `ChunkParser::chunks`.
+pub struct ChunkIter<'a, P, H, R> {
+    parser: &'a mut P,
+    next: u64, // position of the next header
+    total_size: Option<u64>, // found on the first call to `next`
+    done: bool,
+    marker: core::marker::PhantomData<(H, R)>
+}
+
+impl<P, H, R> Iterator for ChunkIter<'_, P, H, R>
+where R: Read + Seek, P: ChunkParser<R> + HeaderParser<H>, H: ChunkHeader {
+    type Item = Result<H>;
+
+    fn next(&mut self) -> Option<Result<H>> {
+        if self.done { return None }
+        let res = (|| {
+            let total_size = match self.total_size {
+                Some(total_size) => total_size,
+                None => *self.total_size.insert(self.parser.reader().seek(SeekFrom::End(0))?)
+            };
+            if self.next >= total_size { return Ok(None) } // all chunks visited
+            self.parser.reader().seek(SeekFrom::Start(self.next))?; // skip the previous body
+            let header = self.parser.header()?;
+            self.parser.validate_header(&header)?;
+            let start = self.parser.reader().stream_position()?;
+            self.next = padded_end(start, header.size(), self.parser.chunk_alignment())?.min(total_size);
+            Ok( Some(header) )
+        })();
+        if !matches!(res, Ok(Some(_))) { self.done = true; }
+        res.transpose()
+    }
+}
+
+/// The `StreamChunkParser` trait parses non-seekable readers.
+///
+/// The size of a stream cannot be found by seeking to the end, so the total
+/// size must be supplied by the caller.
+#[cfg(feature = "std")]
+pub trait StreamChunkParser<R: Read>: ChunkParser<StreamReader<R>> {
+    /// Parse top level chunk(s) from a stream of `total_size` bytes.
+    #[inline]
+    fn parse_stream<H>(&mut self, f: ParserFn<Self,H>, total_size: u64) -> Result<()> where Self: HeaderParser<H>
+        { self.parse_loop(f, total_size) }
+}
+
+// Blanket implementation of stream parsing.
+#[cfg(feature = "std")]
+impl<R: Read, P: ChunkParser<StreamReader<R>>> StreamChunkParser<R> for P {}
+
+//------------------------------------------------------------------------------
+
+/// The `ParserWrite` trait provides typed write API.
+///
+/// The inverse of `ParserRead`, writing the bytes of plain old data types
+/// directly from memory. Integer writes convert to the requested byte order
+/// first, so packed struct layouts can be authored symmetrically to parsing.
+pub trait ParserWrite<W: Write> {
+    /// Access the inner writer.
+    fn writer(&mut self) -> &mut W;
+
+    /// Write a plain old data type.
+    #[inline] fn write<T: Pod>(&mut self, value: &T) -> Result<()>
+        { self.writer().write_raw(value) }
+
+    /// Write the bytes of any sized type.
+    ///
+    /// # Safety
+    ///
+    /// `T` must not contain padding bytes.
+    #[inline] unsafe fn write_unchecked<T: Sized>(&mut self, value: &T) -> Result<()>
+        { self.writer().write_raw_unchecked(value) }
+
+    /// Write a byte buffer.
+    #[inline] fn write_bytes(&mut self, buf: &[u8]) -> Result<()>
+        { Ok( self.writer().write_all(buf)? ) }
+
+    /// Big endian write for all primitive integer types.
+    #[inline] fn write_be<T: PrimInt + Pod>(&mut self, value: T) -> Result<()>
+        { self.write(&value.to_be()) }
+
+    /// Little endian write for all primitive integer types.
+    #[inline] fn write_le<T: PrimInt + Pod>(&mut self, value: T) -> Result<()>
+        { self.write(&value.to_le()) }
+
+    /// Runtime endian write for all primitive integer types.
+    #[inline] fn write_int<T: PrimInt + Pod>(&mut self, value: T, endian: Endian) -> Result<()> {
+        match endian {
+            Endian::Little => self.write_le(value),
+            Endian::Big => self.write_be(value)
+        }
+    }
+}
+
+/// The `ChunkWriter` trait writes header prefixed chunks.
+///
+/// The size field of a chunk precedes a body whose length is usually unknown
+/// until it has been written. `begin_chunk` writes the four character code and
+/// a placeholder size, and `end_chunk` seeks back to patch in the real size.
+/// Open chunks are kept on a stack so nested chunks mirror `subchunks`.
+pub trait ChunkWriter<W: Write + Seek>: ParserWrite<W> {
+    /// Access the inner stack of open chunk size offsets.
+    fn inner_chunks(&mut self) -> &mut Vec<u64>;
+
+    /// Byte order of the chunk size field written by `end_chunk`.
+    ///
+    /// Named apart from `ParserSize::size_endian` and `ParserAlign::alignment`,
+    /// so one type can implement both the reading and the writing traits.
+    fn write_size_endian(&self) -> Endian { Endian::Big }
+
+    /// Chunk alignment in bytes, pad bytes are not counted in the chunk size.
+    fn write_alignment(&self) -> u64 { 1 }
+
+    /// Write a chunk header with a placeholder size.
+    fn begin_chunk(&mut self, fourcc: [u8; 4]) -> Result<()> {
+        self.write(&fourcc)?;
+        let offset = self.writer().stream_position()?;
+        self.write(&0u32)?;
+        self.inner_chunks().push(offset);
+        Ok(())
+    }
+
+    /// Patch the size of the innermost open chunk and pad the body.
+    ///
+    /// Returns the size written to the header.
+    fn end_chunk(&mut self) -> Result<u64> {
+        let offset = self.inner_chunks().pop().ok_or(Error::ParseError)?;
+        let end = self.writer().stream_position()?;
+        let size = end.checked_sub(offset + 4).ok_or(Error::ParseError)?;
+        let size32 = u32::try_from(size).map_err(|_| Error::SizeOverflow)?;
+        self.writer().seek(SeekFrom::Start(offset))?;
+        self.write_int(size32, self.write_size_endian())?;
+        self.writer().seek(SeekFrom::Start(end))?;
+        let pad = size.checked_next_multiple_of(self.write_alignment()).ok_or(Error::SizeOverflow)? - size;
+        for _ in 0..pad { self.write(&0u8)?; }
+        Ok( size )
+    }
+
+    /// Write a complete chunk, with `f` writing the body and any subchunks.
+    fn chunk(&mut self, fourcc: [u8; 4], f: impl FnOnce(&mut Self) -> Result<()>) -> Result<u64> where Self: Sized {
+        self.begin_chunk(fourcc)?;
+        f(self)?;
+        self.end_chunk()
+    }
+}
+
+//------------------------------------------------------------------------------
+
+/// `chunk_parser` prelude.
+pub mod prelude {
+    pub use super::{FourCC, FourCCExt, TypeId, Endian, Pod, ByteSwap, Bookmark, to_size, to_usize};
+    pub use core::ops::ControlFlow;
+    pub use super::{
+        HeaderParser, ChunkHeader, ChunkParser, StandardHeader, SizeConvention,
+        OffsetReader, TapReader, ChunkIter, ChunkNode, ChunkContext, ParseStats,
+        ChunkDispatcher, ChunkWriter, ParserWrite,
+        ParserReader, ParserTake, ParserRead, ParserSeek, ParserPeek, ParserSlice, ParserAlign, ParserChunk,
+        ParserTotalSize, ParserStats, ParserDepth, ParserEndian, ParserSize,
+        ParserFn, ParserAutoFn, ParserFlowFn, ParserContextFn
+    };
+    #[cfg(feature = "std")]
+    pub use super::{StreamReader, StreamChunkParser, ParserPath, ParserOpen};
+    #[cfg(feature = "mmap")]
+    pub use super::ParserMmap;
+    #[cfg(feature = "crc")]
+    pub use super::ParserCrc;
+    #[cfg(feature = "async")]
+    pub use super::async_parser::{
+        AsyncHeaderParser, AsyncChunkParser,
+        AsyncParserRead, AsyncParserSeek,
+        AsyncParserFn
+    };
+    pub use super::chunk_parser;
+}
+
+//==============================================================================
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    mod chunk_parser {
+        pub use super::super::Error;
+        pub use super::super::Result;
+    }
+    use super::prelude::*;
+    use chunk_parser::{Error, Result};
+
+    // full iff parser definition without macro
+    use std::io::{Read, Seek};
+    struct IFFParserFull<R> { reader: R, depth: u8, endian: Endian, end: u64, total: Option<u64>, stats: ParseStats, max_chunks: u64, max_bytes: u64, path: std::path::PathBuf }
+    impl<R: Read> IFFParserFull<R> { fn new(reader: R) -> IFFParserFull<R> { IFFParserFull{ reader, depth: 0, endian: Endian::Big, end: 0, total: None, stats: Default::default(), max_chunks: u64::MAX, max_bytes: u64::MAX, path: Default::default() } } }
+    impl<R> ParserReader<R> for IFFParserFull<R> { fn reader(&mut self) -> &mut R { &mut self.reader } }
+    impl<R: Seek> ParserSeek<R> for IFFParserFull<R> {}
+    impl<R: Read> ParserRead<R> for IFFParserFull<R> {}
+    impl<R> ParserDepth for IFFParserFull<R> { fn inner_depth(&mut self) -> &mut u8 { &mut self.depth } }
+    impl<R: Read> ParserEndian<R> for IFFParserFull<R> { fn inner_endian(&mut self) -> &mut Endian { &mut self.endian } }
+    impl<R: Seek> ParserChunk<R> for IFFParserFull<R> { fn inner_chunk_end(&mut self) -> &mut u64 { &mut self.end } }
+    impl<R: Seek> ParserTotalSize<R> for IFFParserFull<R> {
+        fn cached_total_size(&self) -> Option<u64> { self.total }
+        fn inner_total_size(&mut self) -> &mut Option<u64> { &mut self.total }
+    }
+    impl<R> ParserStats for IFFParserFull<R> {
+        fn stats(&self) -> &ParseStats { &self.stats }
+        fn stats_mut(&mut self) -> &mut ParseStats { &mut self.stats }
+        fn max_chunks(&self) -> u64 { self.max_chunks }
+        fn max_bytes(&self) -> u64 { self.max_bytes }
+    }
+    #[cfg(feature = "mmap")]
+    impl ParserMmap for IFFParserFull<std::io::Cursor<memmap2::Mmap>> {
+        fn from_mmap(reader: std::io::Cursor<memmap2::Mmap>, path: std::path::PathBuf) -> Self { IFFParserFull { path, ..IFFParserFull::new(reader) } }
+    }
+    impl<R> ParserPath for IFFParserFull<R> { fn path(&self) -> &std::path::PathBuf { &self.path } }
+    impl ParserOpen for IFFParserFull<std::io::BufReader<std::fs::File>> {
+        fn from_file(reader: std::io::BufReader<std::fs::File>, path: std::path::PathBuf) -> Self { IFFParserFull { path, ..IFFParserFull::new(reader) } }
+    }
+    impl<R: Read + Seek> ChunkParser<R> for IFFParserFull<R> {
+        fn record_total_size(&mut self, total_size: u64) { *self.inner_total_size() = Some(total_size) }
+        fn charge(&mut self, context: &ChunkContext, bytes: u64) -> Result<()> { self.charge_chunk(context, bytes) }
+    }
+
+    // Simple header definition.
+    struct IFFHeader { typeid: TypeId, length: u32 }
+    crate::impl_chunk_header!(IFFHeader, fourcc: typeid, size: length);
+    impl<R: Read> HeaderParser<IFFHeader> for IFFParserFull<R> {
+        fn header(&mut self) -> Result<IFFHeader>
+            { Ok( IFFHeader { typeid: self.read()?, length: self.read_be()? } ) }
+        fn as_chunk_header<'a>(&self, header: &'a IFFHeader) -> Option<&'a dyn ChunkHeader> { Some(header) }
+    }
+
+    // parser storing the reader as an option
+    struct IFFParserTake<R> { reader: Option<R> }
+    impl<R> ParserReader<R> for IFFParserTake<R> { fn reader(&mut self) -> &mut R { self.reader.as_mut().expect("reader taken") } }
+    impl<R> ParserTake<R> for IFFParserTake<R> { fn inner_reader(&mut self) -> &mut Option<R> { &mut self.reader } }
+    impl<R: Read> ParserRead<R> for IFFParserTake<R> {}
+
+    // packed layout with misaligned fields
+    #[repr(C, packed)]
+    #[derive(Clone, Copy)]
+    struct PackedHeader { tag: u8, length: u32, flags: u16 }
+    unsafe impl Pod for PackedHeader {}
+
+    impl ByteSwap for PackedHeader {
+        fn swap_bytes(self) -> Self
+            { PackedHeader { tag: self.tag, length: self.length.swap_bytes(), flags: self.flags.swap_bytes() } }
+    }
+
+    // iff writer definition
+    struct IFFWriter<W> { writer: W, chunks: Vec<u64> }
+    impl<W: std::io::Write> ParserWrite<W> for IFFWriter<W> { fn writer(&mut self) -> &mut W { &mut self.writer } }
+    impl<W: std::io::Write + Seek> ChunkWriter<W> for IFFWriter<W> {
+        fn inner_chunks(&mut self) -> &mut Vec<u64> { &mut self.chunks }
+    }
+
+    // parser rejecting subchunks with a validation hook
+    #[chunk_parser]
+    struct StrictParser;
+    impl<R: Read> HeaderParser<IFFHeader> for StrictParser<R> {
+        fn header(&mut self) -> Result<IFFHeader>
+            { Ok( IFFHeader { typeid: self.read()?, length: self.read_be()? } ) }
+        fn validate_header(&mut self, header: &IFFHeader) -> Result<()>
+            { if header.typeid == *b"FORM" { Ok(()) } else { Err(Error::InvalidFourCC) } }
+        fn as_chunk_header<'a>(&self, header: &'a IFFHeader) -> Option<&'a dyn ChunkHeader> { Some(header) }
+    }
+
+    // parser with a header consuming no bytes
+    #[chunk_parser]
+    struct EmptyParser;
+    struct EmptyHeader;
+    impl<R: Read> HeaderParser<EmptyHeader> for EmptyParser<R> {
+        fn header(&mut self) -> Result<EmptyHeader> { Ok( EmptyHeader ) }
+    }
+
+    // iff parser without the as_chunk_header hook
+    #[chunk_parser]
+    struct BareParser;
+    impl<R: Read> HeaderParser<IFFHeader> for BareParser<R> {
+        fn header(&mut self) -> Result<IFFHeader>
+            { Ok( IFFHeader { typeid: self.read()?, length: self.read_be()? } ) }
+    }
+
+    // minimal iff parser definition with macro
+    #[chunk_parser]
+    struct IFFParser;
+    impl<R: Read> HeaderParser<IFFHeader> for IFFParser<R> {
+        fn header(&mut self) -> Result<IFFHeader>
+            { Ok( IFFHeader { typeid: self.read()?, length: self.read_be()? } ) }
+        fn as_chunk_header<'a>(&self, header: &'a IFFHeader) -> Option<&'a dyn ChunkHeader> { Some(header) }
+    }
+
+    // minimal riff parser definition with little endian sizes
+    #[chunk_parser]
+    struct RIFFParser;
+    impl<R: Read> HeaderParser<IFFHeader> for RIFFParser<R> {
+        fn header(&mut self) -> Result<IFFHeader>
+            { Ok( IFFHeader { typeid: self.read()?, length: self.read_le()? } ) }
+        fn as_chunk_header<'a>(&self, header: &'a IFFHeader) -> Option<&'a dyn ChunkHeader> { Some(header) }
+    }
+    impl<R: Seek> ParserAlign<R> for RIFFParser<R> { fn alignment(&self) -> u64 { 2 } }
+    impl<R: Read> ParserSize<R> for RIFFParser<R> {
+        type ChunkSize = u32;
+        fn size_endian(&self) -> Endian { Endian::Little }
+    }
+
+    // standard header parser definition
+    #[chunk_parser]
+    struct StandardParser;
+    impl<R: Read> HeaderParser<StandardHeader> for StandardParser<R> {
+        fn header(&mut self) -> Result<StandardHeader>
+            { self.read_standard_header(Endian::Big) }
+        fn as_chunk_header<'a>(&self, header: &'a StandardHeader) -> Option<&'a dyn ChunkHeader> { Some(header) }
+    }
+
+    // header inclusive size parser definition
+    #[chunk_parser]
+    struct InclusiveParser;
+    impl<R: Read> HeaderParser<StandardHeader> for InclusiveParser<R> {
+        fn header(&mut self) -> Result<StandardHeader>
+            { self.read_standard_header_with(Endian::Big, SizeConvention::Chunk) }
+        fn as_chunk_header<'a>(&self, header: &'a StandardHeader) -> Option<&'a dyn ChunkHeader> { Some(header) }
+    }
+
+    // parser with a six byte header and 16-bit sizes
+    #[chunk_parser]
+    struct ShortParser;
+    struct ShortHeader { typeid: TypeId, length: u16 }
+    crate::impl_chunk_header!(ShortHeader, fourcc: typeid, size: length);
+    impl<R: Read> HeaderParser<ShortHeader> for ShortParser<R> {
+        fn header(&mut self) -> Result<ShortHeader>
+            { Ok( ShortHeader { typeid: self.read()?, length: self.read_be()? } ) }
+        fn as_chunk_header<'a>(&self, header: &'a ShortHeader) -> Option<&'a dyn ChunkHeader> { Some(header) }
+    }
+
+    // minimal custom parser loop
+    #[chunk_parser(custom,depth)]
+    struct IFFParserCustom;
+    impl<R: Read> HeaderParser<IFFHeader> for IFFParserCustom<R> {
+        fn header(&mut self) -> Result<IFFHeader>
+            { Ok( IFFHeader { typeid: self.read()?, length: self.read_be::<u32>()? - 8 } ) }
+    }
+    impl<R: std::io::Read + std::io::Seek> ChunkParser<R> for IFFParserCustom<R> {
+        fn parse_loop<H>(&mut self, f: ParserFn<Self,H>, total_size: u64) -> Result<()> where Self: HeaderParser<H> {
+            self.push();
+            match loop {
+                let header = self.header()?;
+                let start = self.reader().stream_position()?;
+                let size = f(self, &header)? + 8; // the parser function is responsible for parsing the size
+                let end = start + size;
+                let pos = self.reader().stream_position()?;
+                if pos == total_size { break Ok(()) } // function consumed chunk
+                else if pos != end { break Err(Error::ParseError) } // function made a mistake
+            } {
+                res => { self.pop(); res }
+            }
+        }
+    }
+
+    // nonsense data to test basic functionality
+    const DATA: &[u8;24] = &[
+        // FORM chunk (24 bytes)
+        0x46, 0x4f, 0x52, 0x4d, // "FORM" chunk typeid
+        0x00, 0x00, 0x00, 0x10, // Chunk size (16 bytes)
+        0x54, 0x45, 0x53, 0x54, // Subchunk typeid ("TEST")
+
+        // TEST chunk (12 bytes)
+        0x54, 0x45, 0x53, 0x54, // "TEST" chunk typid
+        0x00, 0x00, 0x00, 0x04, // Chunk size (4 bytes)
+        0x01, 0x02, 0x03, 0x04, // Test data
+    ];
+
+    #[test]
+    fn without_macro() -> Result<()> {
+        let mut cursor = std::io::Cursor::new(DATA);
+        let mut iff = IFFParserFull::new(&mut cursor);
+        iff.parse(|parser, header| {
+            if &header.typeid != b"FORM" { panic!(); }
+            parser.skip(header.length as u64)
+        })
+    }
+
+    #[test]
+    fn with_macro() -> Result<()> {
+        let mut cursor = std::io::Cursor::new(DATA);
+        let mut iff = IFFParser::new(&mut cursor);
+        iff.parse(|parser, header| parser.skip(header.length as u64))
+    }
+
+    #[test]
+    fn cursor() -> Result<()> {
+        IFFParser::cursor(DATA).parse(|parser, header| parser.skip(header.length as u64))
+    }
+
+    #[test]
+    fn custom() -> Result<()> {
+        let mut iff = IFFParserCustom::cursor(DATA);
+        iff.parse(|parser, header| parser.skip(header.length as u64 + 8))
+    }
+
+    #[test]
+    fn read_le() -> Result<()> {
+        let mut iff = IFFParser::cursor(&DATA[4..8]);
+        assert_eq!(iff.read_le::<u32>()?, 0x10000000);
+        Ok(())
+    }
+
+    #[test]
+    fn read_be_signed() -> Result<()> {
+        let data = [0xff, 0xfe, 0x80, 0x00, 0x00, 0x01, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xfd];
+        let mut iff = IFFParser::cursor(&data[..]);
+        assert_eq!(iff.read_be::<i16>()?, -2);
+        assert_eq!(iff.read_be::<i32>()?, -0x7fffffff);
+        assert_eq!(iff.read_be::<i64>()?, -3);
+        iff.seek(0)?;
+        assert_eq!(iff.read_array_be::<i16, 3>()?, [-2, -0x8000, 0x0001]);
+        let data = [&[0xff; 15][..], &[0x80]].concat();
+        let mut iff = IFFParser::cursor(&data[..]);
+        assert_eq!(iff.read_be::<i128>()?, -0x80);
+        iff.seek(0)?;
+        assert_eq!(iff.read_be::<u128>()?, u128::MAX - 0x7f);
+        Ok(())
+    }
+
+    #[test]
+    fn read_uint() -> Result<()> {
+        let data = [0x01, 0x02, 0x03, 0xff, 0xff, 0xfe, 0x80, 0x00, 0x00];
+        let mut iff = IFFParser::cursor(&data[..]);
+        assert_eq!(iff.read_u24_le()?, 0x030201);
+        iff.seek(0)?;
+        assert_eq!(iff.read_u24_be()?, 0x010203);
+        assert_eq!(iff.read_sint(3, Endian::Big)?, -2);
+        assert_eq!(iff.read_sint(3, Endian::Little)?, 0x80);
+        iff.seek(6)?;
+        assert_eq!(iff.read_sint(3, Endian::Big)?, -0x800000);
+        iff.seek(0)?;
+        assert_eq!(iff.read_uint(8, Endian::Little)?, 0x0080feffff030201);
+        assert!(matches!(iff.read_uint(9, Endian::Big), Err(Error::SizeOverflow)));
+        assert!(matches!(iff.read_uint(2, Endian::Big), Err(Error::UnexpectedEof)));
+        Ok(())
+    }
+
+    #[test]
+    fn read_enum() -> Result<()> {
+        #[derive(Debug, PartialEq)]
+        enum Kind { Form, List }
+        impl TryFrom<u16> for Kind {
+            type Error = u16;
+            fn try_from(value: u16) -> std::result::Result<Self, u16> {
+                match value { 0 => Ok(Kind::Form), 1 => Ok(Kind::List), n => Err(n) }
+            }
+        }
+        let mut iff = IFFParser::cursor(&[0x00, 0x02, 0x01, 0x00, 0x00, 0x01][..]);
+        assert!(!iff.read_bool()?);
+        assert!(iff.read_bool()?); // any nonzero byte
+        assert_eq!(iff.read_enum::<Kind, u16>(Endian::Little)?, Kind::List);
+        assert_eq!(iff.read_enum::<Kind, u16>(Endian::Big)?, Kind::List);
+        iff.seek(1)?;
+        assert!(matches!(iff.read_enum::<Kind, u16>(Endian::Big), Err(Error::ParseError)));
+        Ok(())
+    }
+
+    #[test]
+    fn read_hashed() -> Result<()> {
+        use std::hash::{Hasher, DefaultHasher};
+        let mut iff = IFFParser::cursor(DATA);
+        let mut hasher = DefaultHasher::new();
+        let typeid = iff.read_hashed::<TypeId, _>(&mut hasher)?;
+        let length = iff.read_hashed::<u32, _>(&mut hasher)?;
+        let mut expected = DefaultHasher::new();
+        expected.write(&DATA[..8]);
+        assert!(typeid == *b"FORM");
+        assert_eq!(u32::from_be(length), 0x10);
+        assert_eq!(hasher.finish(), expected.finish());
+        Ok(())
+    }
+
+    #[test]
+    fn tap_reader() -> Result<()> {
+        let mut tapped = Vec::new();
+        IFFParser::new(TapReader::new(std::io::Cursor::new(DATA), |bytes: &[u8]| tapped.extend_from_slice(bytes))).parse(|parser, header| {
+            parser.skip(4)?; // skipped bytes are not tapped
+            parser.subchunks(|parser, header| {
+                parser.read_be::<u32>()?;
+                Ok(header.length as u64)
+            }, 12)?;
+            Ok(header.length as u64)
+        })?;
+        assert_eq!(tapped, [&DATA[..8], &DATA[12..]].concat());
+        Ok(())
+    }
+
+    #[test]
+    fn read_float() -> Result<()> {
+        let data = [0x3f, 0x80, 0x00, 0x00, 0x00, 0x00, 0x80, 0x3f];
+        let mut iff = IFFParser::cursor(&data[..]);
+        assert_eq!(iff.read_f32_be()?, 1.0);
+        assert_eq!(iff.read_f32_le()?, 1.0);
+        let data = [0xc0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+        assert_eq!(IFFParser::cursor(&data[..]).read_f64_be()?, -2.0);
+        let data = [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xf0, 0x3f];
+        assert_eq!(IFFParser::cursor(&data[..]).read_f64_le()?, 1.0);
+        Ok(())
+    }
+
+    #[test]
+    fn read_f80_be() -> Result<()> {
+        let rate = [0x40, 0x0e, 0xac, 0x44, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]; // 44100 Hz
+        assert_eq!(IFFParser::cursor(&rate[..]).read_f80_be()?, 44100.0);
+        let negative = [0xbf, 0xff, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+        assert_eq!(IFFParser::cursor(&negative[..]).read_f80_be()?, -1.0);
+        let infinity = [0x7f, 0xff, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+        assert_eq!(IFFParser::cursor(&infinity[..]).read_f80_be()?, f64::INFINITY);
+        assert!(IFFParser::cursor(&[0x7f, 0xff, 0xc0, 0, 0, 0, 0, 0, 0, 0][..]).read_f80_be()?.is_nan());
+        assert_eq!(IFFParser::cursor(&[0; 10][..]).read_f80_be()?, 0.0);
+        Ok(())
+    }
+
+    #[test]
+    fn read_bounded() -> Result<()> {
+        let mut iff = IFFParser::cursor(DATA);
+        let mut remaining = 6;
+        let _: u32 = iff.read_bounded(&mut remaining)?;
+        assert_eq!(remaining, 2);
+        assert!(matches!(iff.read_bounded::<u32>(&mut remaining), Err(Error::ChunkOverrun)));
+        assert_eq!(iff.position()?, 4); // nothing was consumed by the failed read
+        Ok(())
+    }
+
+    #[test]
+    fn copy_to() -> Result<()> {
+        let mut iff = IFFParser::cursor(DATA);
+        let mut body = Vec::new();
+        iff.skip(20)?;
+        assert_eq!(iff.copy_to(4, &mut body)?, 4);
+        assert_eq!(body, [0x01, 0x02, 0x03, 0x04]);
+        iff.seek(20)?;
+        assert!(matches!(iff.copy_to(8, &mut body), Err(Error::UnexpectedEof)));
+        iff.seek(0)?;
+        std::io::copy(&mut iff.reader().take(4), &mut body)?;
+        assert_eq!(&body[8..], b"FORM");
+        Ok(())
+    }
+
+    #[test]
+    fn read_bytes() -> Result<()> {
+        let mut iff = IFFParser::cursor(DATA);
+        assert_eq!(iff.read_bytes(4)?, b"FORM");
+        assert!(matches!(iff.read_bytes(24), Err(Error::UnexpectedEof)));
+        assert!(matches!(IFFParser::cursor(DATA).read_bytes(usize::MAX / 2), Err(Error::UnexpectedEof))); // not allocated up front
+        Ok(())
+    }
+
+    #[test]
+    fn read_into() -> Result<()> {
+        let mut iff = IFFParser::cursor(DATA);
+        let mut buf = [0; 4];
+        iff.read_into(&mut buf)?;
+        assert_eq!(&buf, b"FORM");
+        iff.read_into(&mut buf)?;
+        assert_eq!(buf, [0x00, 0x00, 0x00, 0x10]);
+        Ok(())
+    }
+
+    #[test]
+    fn read_array() -> Result<()> {
+        let mut iff = IFFParser::cursor(DATA);
+        assert_eq!(&iff.read_array::<u8, 4>()?, b"FORM");
+        assert_eq!(iff.read_array_be::<u16, 2>()?, [0x0000, 0x0010]);
+        assert!(iff.read_array::<u32, 5>().is_err()); // only 16 bytes remain
+        Ok(())
+    }
+
+    #[test]
+    fn read_vec() -> Result<()> {
+        let mut iff = IFFParser::cursor(DATA);
+        assert_eq!(iff.read_vec::<u8>(4)?, b"FORM");
+        assert_eq!(iff.read_vec_be::<u32>(1)?, [0x10]);
+        assert!(matches!(iff.read_vec::<u32>(usize::MAX), Err(Error::SizeOverflow)));
+        assert!(matches!(iff.read_vec::<u32>(isize::MAX as usize / 4), Err(Error::SizeOverflow))); // unallocatable
+        Ok(())
+    }
+
+    #[test]
+    fn read_cstring() -> Result<()> {
+        let data: &[u8] = &[ 0x54, 0x45, 0x53, 0x54, 0x00, 0xff, 0x00 ];
+        let mut iff = IFFParser::cursor(data);
+        assert_eq!(iff.read_cstring()?, "TEST");
+        assert_eq!(iff.position()?, 5);
+        assert!(matches!(iff.read_cstring(), Err(Error::InvalidUtf8)));
+        Ok(())
+    }
+
+    #[test]
+    fn read_string() -> Result<()> {
+        let data: &[u8] = &[ 0x54, 0x45, 0x53, 0x54, 0x20, 0x00, 0x20, 0x00 ];
+        let mut iff = IFFParser::cursor(data);
+        assert_eq!(iff.read_string(8)?, "TEST");
+        assert_eq!(iff.position()?, 8);
+        iff.seek(0)?;
+        assert_eq!(iff.read_string_raw(8)?, data);
+        Ok(())
+    }
+
+    #[test]
+    fn read_pascal_string() -> Result<()> {
+        let data: &[u8] = &[ 0x04, 0x54, 0x45, 0x53, 0x54, 0x00, 0x01, 0xff ];
+        let mut iff = IFFParser::cursor(data);
+        assert_eq!(iff.read_pascal_string_padded()?, "TEST");
+        assert_eq!(iff.position()?, 6);
+        assert!(matches!(iff.read_pascal_string(), Err(Error::InvalidUtf8)));
+        Ok(())
+    }
+
+    #[test]
+    fn read_fourcc() -> Result<()> {
+        let mut iff = IFFParser::cursor(DATA);
+        assert!(iff.read_fourcc_checked()? == *b"FORM");
+        assert!(matches!(iff.read_fourcc_checked(), Err(Error::InvalidFourCC)));
+        iff.seek(4)?;
+        assert!(iff.read_fourcc()? == [0x00, 0x00, 0x00, 0x10]);
+        Ok(())
+    }
+
+    #[test]
+    fn peek() -> Result<()> {
+        let mut iff = IFFParser::cursor(DATA);
+        iff.seek(20)?;
+        assert_eq!(iff.peek::<u16>()?, 0x0201);
+        assert!(iff.peek::<u64>().is_err());
+        assert_eq!(iff.position()?, 20);
+        iff.seek(8)?;
+        assert!(iff.peek_fourcc()? == *b"TEST");
+        assert_eq!(iff.position()?, 8);
+        Ok(())
+    }
+
+    #[test]
+    fn read_pod() -> Result<()> {
+        #[repr(C)]
+        struct Header { typeid: TypeId, length: [u8; 4] }
+        unsafe impl Pod for Header {}
+        let mut iff = IFFParser::cursor(DATA);
+        let header: Header = iff.read()?;
+        assert!(header.typeid == *b"FORM");
+        assert_eq!(u32::from_be_bytes(header.length), 0x10);
+        Ok(())
+    }
+
+    #[cfg(feature = "bytemuck")]
+    #[test]
+    fn read_pod_bytemuck() -> Result<()> {
+        let mut iff = IFFParser::cursor(DATA);
+        assert_eq!(&iff.read_pod::<[u8; 4]>()?, b"FORM");
+        assert_eq!(iff.read_pod_slice::<[u8; 4]>(2)?, [[0x00, 0x00, 0x00, 0x10], *b"TEST"]);
+        Ok(())
+    }
+
+    #[test]
+    fn error_display() {
+        use std::error::Error as _;
+        let eof = Error::from(std::io::Error::from(std::io::ErrorKind::UnexpectedEof));
+        assert!(matches!(eof, Error::UnexpectedEof));
+        let io = Error::from(std::io::Error::from(std::io::ErrorKind::PermissionDenied));
+        assert!(io.source().is_some());
+        assert_eq!(Error::SizeOverflow.to_string(), "size overflow");
+        assert!(Error::SizeOverflow.source().is_none());
+    }
+
+    #[test]
+    fn chunk_error() {
+        let mut iff = IFFParser::cursor(DATA);
+        let res = iff.parse(|parser, header| { parser.skip(4)?; Ok(header.length as u64) });
+        let err = res.unwrap_err();
+        assert!(matches!(err, Error::Chunk { fourcc: Some(fourcc), offset: 0, .. } if fourcc == *b"FORM"));
+        assert!(matches!(err.kind(), Error::ChunkSizeMismatch { expected_end: 24, actual_pos: 12 }));
+        assert_eq!(Error::ParseError.in_chunk(None, 0).to_string(), "parse error at offset 0x0");
+        let err = EmptyParser::cursor(DATA).parse(|_, _| Ok(0)).unwrap_err();
+        assert!(matches!(err, Error::Chunk { fourcc: None, .. })); // header without a chunk type
+    }
+
+    #[test]
+    fn chunk_size_mismatch() -> Result<()> {
+        let err = IFFParser::cursor(DATA).parse(|parser, header| {
+            parser.skip(4)?; // form type
+            parser.subchunks(|parser, header| { parser.skip(2)?; Ok(header.length as u64) }, header.length as u64 - 4)?;
+            Ok(header.length as u64)
+        }).unwrap_err();
+        assert!(matches!(err, Error::Chunk { offset: 12, .. }));
+        assert!(matches!(err.kind(), Error::ChunkSizeMismatch { expected_end: 24, actual_pos: 22 }));
+        assert_eq!(err.kind().to_string(), "chunk size mismatch: expected end 0x18, found 0x16");
+        Ok(())
+    }
+
+    #[test]
+    fn parse_lenient() -> Result<()> {
+        let skipped = IFFParser::cursor(DATA).parse_lenient(|parser, header| Err(parser.unknown_chunk(header)))?;
+        assert_eq!(skipped.len(), 1);
+        assert!(skipped[0].typeid == *b"FORM");
+        let err = IFFParser::cursor(DATA).parse::<IFFHeader>(|parser, header| Err(parser.unknown_chunk(header))).unwrap_err();
+        assert!(matches!(err.kind(), Error::UnknownChunk { offset: 8, .. }));
+        Ok(())
+    }
+
+    // riff style chunks with an odd length padded to an even boundary
+    const RIFF_DATA: &[u8;22] = &[
+        // ODD chunk (12 bytes)
+        0x4f, 0x44, 0x44, 0x20, // "ODD " chunk typeid
+        0x03, 0x00, 0x00, 0x00, // Chunk size (3 bytes)
+        0x01, 0x02, 0x03, 0x00, // Test data and pad byte
+
+        // EVEN chunk (10 bytes)
+        0x45, 0x56, 0x45, 0x4e, // "EVEN" chunk typeid
+        0x02, 0x00, 0x00, 0x00, // Chunk size (2 bytes)
+        0x01, 0x02, // Test data
+    ];
+
+    #[test]
+    fn parse_padded() -> Result<()> {
+        RIFFParser::cursor(RIFF_DATA).parse_padded(|parser, header| parser.skip(header.length as u64), 2)?;
+        assert!(RIFFParser::cursor(RIFF_DATA).parse(|parser, header| parser.skip(header.length as u64)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn parse_aligned() -> Result<()> {
+        let mut riff = RIFFParser::cursor(RIFF_DATA);
+        riff.parse_aligned(|parser, header| parser.skip(header.length as u64))?;
+        riff.seek(11)?;
+        assert_eq!(riff.align(8)?, 1);
+        assert_eq!(riff.align(8)?, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn chunk_remaining() -> Result<()> {
+        let mut iff = IFFParserFull::new(std::io::Cursor::new(DATA));
+        iff.parse_tracked(|parser, header| {
+            assert_eq!(parser.chunk_remaining()?, 16);
+            parser.skip(4)?;
+            parser.subchunks_tracked(|parser, header| {
+                while parser.chunk_remaining()? > 0 { parser.read::<u8>()?; }
+                Ok(header.length as u64)
+            }, 12)?;
+            assert_eq!(parser.chunk_remaining()?, 0);
+            Ok(header.length as u64)
+        })
+    }
+
+    #[test]
+    fn skip_chunk_remaining() -> Result<()> {
+        let mut iff = IFFParserFull::new(std::io::Cursor::new(DATA));
+        iff.parse_tracked(|parser, header| {
+            parser.skip(4)?;
+            parser.subchunks_tracked(|parser, header| {
+                parser.read::<u8>()?; // only the first field is understood
+                assert_eq!(parser.skip_chunk_remaining()?, 3);
+                Ok(header.length as u64)
+            }, 12)?;
+            Ok(header.length as u64)
+        })
+    }
+
+    #[test]
+    fn chunk_reader() -> Result<()> {
+        let mut iff = IFFParser::cursor(DATA);
+        let bytes = iff.with_chunk_reader(8, |reader| {
+            let mut bytes = Vec::new();
+            reader.take(4).read_to_end(&mut bytes)?;
+            Ok( bytes )
+        })?;
+        assert_eq!(bytes, b"FORM");
+        assert_eq!(iff.position()?, 8);
+        let mut bytes = Vec::new();
+        iff.chunk_reader(4).read_to_end(&mut bytes)?;
+        assert_eq!(bytes, b"TEST");
+        Ok(())
+    }
+
+    #[test]
+    fn read_size() -> Result<()> {
+        let mut riff = RIFFParser::cursor(RIFF_DATA);
+        riff.seek(4)?;
+        assert_eq!(riff.read_size()?, 3);
+        Ok(())
+    }
+
+    #[test]
+    fn size_overflow() {
+        let mut iff = IFFParser::cursor(DATA);
+        let err = iff.parse(|_, _| Ok(u64::MAX)).unwrap_err();
+        assert!(matches!(err.kind(), Error::SizeOverflow));
+        let mut iff = IFFParser::cursor(DATA);
+        let err = iff.parse(|parser, _| { parser.subchunks::<IFFHeader>(|_, _| Ok(0), u64::MAX)?; Ok(0) }).unwrap_err();
+        assert!(matches!(err.kind(), Error::SizeOverflow));
+    }
+
+    #[test]
+    fn max_depth() {
+        // recurse into the same chunk until the depth limit is hit
+        fn nested(parser: &mut IFFParser<std::io::Cursor<&[u8]>>, _: &IFFHeader) -> Result<u64> {
+            parser.rewind(8)?;
+            parser.subchunks(nested, 24)?;
+            Ok(0)
+        }
+        let err = IFFParser::new(std::io::Cursor::new(&DATA[..])).parse(nested).unwrap_err();
+        assert!(matches!(err.kind(), Error::DepthExceeded));
+    }
+
+    #[test]
+    #[cfg_attr(debug_assertions, should_panic)]
+    fn pop_underflow() {
+        let mut iff = IFFParser::cursor(DATA);
+        iff.pop();
+        assert_eq!(iff.depth(), 0);
+    }
+
+    #[test]
+    fn open() -> Result<()> {
+        let path = std::env::temp_dir().join("chunk-parser-open.iff");
+        std::fs::write(&path, DATA)?;
+        let mut iff = IFFParserFull::open(&path)?;
+        assert_eq!(iff.path(), &path);
+        iff.parse(|parser, header| parser.skip(header.length as u64))?;
+        std::fs::remove_file(&path)?;
+        assert!(matches!(IFFParserFull::open(&path), Err(Error::IoError(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn open_with_capacity() -> Result<()> {
+        let path = std::env::temp_dir().join("chunk-parser-open-capacity.iff");
+        std::fs::write(&path, DATA)?;
+        let mut iff = IFFParserFull::open_with_capacity(&path, 16)?;
+        assert_eq!(iff.reader().capacity(), 16);
+        iff.parse(|parser, header| parser.skip(header.length as u64))?;
+        assert_eq!(IFFParserFull::open(&path)?.reader().capacity(), 64 * 1024);
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn standard_header() -> Result<()> {
+        StandardParser::cursor(DATA).parse(|parser, header| {
+            assert!(header.fourcc == *b"FORM");
+            parser.skip(header.size())
+        })
+    }
+
+    #[test]
+    fn size_convention() -> Result<()> {
+        let mut data = DATA.to_vec();
+        data[7] = 0x18; // FORM size including its header
+        data[19] = 0x0c; // TEST size including its header
+        InclusiveParser::cursor(&data[..]).parse_auto(|parser, header| {
+            assert_eq!(header.size(), 16);
+            parser.skip(4)?;
+            parser.subchunks_auto(|parser, header| {
+                assert_eq!(header.size(), 4);
+                assert_eq!(parser.read_be::<u32>()?, 0x01020304);
+                Ok(())
+            }, 12)
+        })?;
+        assert_eq!(SizeConvention::Body.body_size(4, 8)?, 4);
+        assert!(matches!(SizeConvention::Chunk.body_size(4, 8), Err(Error::ParseError)));
+        assert_eq!(StandardHeader::SIZE, 8);
+        Ok(())
+    }
+
+    #[test]
+    fn short_header() -> Result<()> {
+        let data = [
+            0x46, 0x4f, 0x52, 0x4d, 0x00, 0x0a, // "FORM" chunk (10 bytes)
+            0x54, 0x45, 0x53, 0x54, 0x00, 0x04, // "TEST" chunk (4 bytes)
+            0x01, 0x02, 0x03, 0x04              // Test data
+        ];
+        ShortParser::cursor(&data[..]).parse(|parser, header| {
+            assert!(header.typeid == *b"FORM");
+            parser.subchunks(|parser, header| {
+                assert!(header.typeid == *b"TEST");
+                assert_eq!(parser.read_be::<u32>()?, 0x01020304);
+                Ok(header.length as u64)
+            }, header.length as u64)?;
+            Ok(header.length as u64)
+        })
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn mmap() -> Result<()> {
+        let path = std::env::temp_dir().join("chunk-parser-mmap.iff");
+        std::fs::write(&path, DATA)?;
+        let mut iff = IFFParserFull::mmap(&path)?;
+        iff.parse(|parser, header| parser.skip(header.length as u64))?;
+        drop(iff.take_reader()?);
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn read_only_reader() -> Result<()> {
+        let mut iff = IFFParser::new(&DATA[..]); // implements Read but not Seek
+        assert!(iff.read::<TypeId>()? == *b"FORM");
+        assert_eq!(iff.read_be::<u32>()?, 0x10);
+        Ok(())
+    }
+
+    #[test]
+    fn parse_stream() -> Result<()> {
+        let mut iff = IFFParser::new(StreamReader::new(&DATA[..]));
+        iff.parse_stream(|parser, header| {
+            parser.skip(4)?;
+            parser.subchunks(|parser, header| parser.skip(header.length as u64), 12)?;
+            Ok(header.length as u64)
+        }, 24)?;
+        let mut iff = IFFParser::new(StreamReader::new(&DATA[..]));
+        assert!(iff.parse(|parser, header| parser.skip(header.length as u64)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn bookmark() -> Result<()> {
+        let mut iff = IFFParser::cursor(DATA);
+        iff.seek(8)?;
+        let bookmark = iff.bookmark()?;
+        iff.skip(8)?;
+        assert_eq!(iff.restore(bookmark)?, 8);
+        let res = iff.with_bookmark(|parser| { parser.seek(20)?; parser.read::<u64>() });
+        assert!(res.is_err());
+        assert_eq!(iff.position()?, 8);
+        Ok(())
+    }
+
+    #[test]
+    fn chunks() -> Result<()> {
+        let data = [&DATA[12..], &DATA[12..]].concat(); // two TEST chunks
+        let mut iff = IFFParser::cursor(&data[..]);
+        let headers = iff.chunks::<IFFHeader>().collect::<Result<Vec<_>>>()?;
+        assert_eq!(headers.len(), 2);
+        assert!(headers[1].typeid == *b"TEST");
+        let mut iff = IFFParser::cursor(DATA);
+        let mut chunks = iff.chunks::<IFFHeader>();
+        assert!(chunks.next().unwrap()?.typeid == *b"FORM");
+        assert!(chunks.next().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn parse_auto() -> Result<()> {
+        IFFParser::cursor(DATA).parse_auto(|parser, _| { parser.skip(4)?; Ok(()) })?;
+        let err = IFFParser::cursor(DATA).parse_auto::<IFFHeader>(|parser, _| { parser.skip(20)?; Ok(()) }).unwrap_err();
+        assert!(matches!(err.kind(), Error::ChunkOverrun));
+        assert!(matches!(err, Error::Chunk { fourcc: Some(fourcc), offset: 0, .. } if fourcc == *b"FORM"));
+        Ok(())
+    }
+
+    #[test]
+    fn parse_documents() -> Result<()> {
+        let data = [&DATA[..], &DATA[12..], &DATA[..]].concat(); // FORM, TEST, FORM
+        let count = IFFParser::cursor(&data[..]).parse_documents(|parser, header: &IFFHeader| {
+            assert_eq!(parser.depth(), 0);
+            assert!(header.typeid == *b"FORM" || header.typeid == *b"TEST");
+            Ok(())
+        })?;
+        assert_eq!(count, 3);
+        assert_eq!(IFFParser::cursor(&[][..]).parse_documents::<IFFHeader>(|_, _| Ok(()))?, 0);
+        let err = IFFParser::cursor(&data[..50]).parse_documents::<IFFHeader>(|_, _| Ok(())).unwrap_err();
+        assert!(matches!(err, Error::Chunk { offset: 36, .. }));
+        Ok(())
+    }
+
+    #[test]
+    fn subchunks_auto() -> Result<()> {
+        IFFParser::cursor(DATA).parse_auto(|parser, header: &IFFHeader| {
+            assert!(header.typeid == *b"FORM");
+            parser.skip(4)?;
+            parser.subchunks_auto(|parser, header| {
+                assert!(header.typeid == *b"TEST");
+                assert_eq!(parser.depth(), 1);
+                assert_eq!(parser.read::<u8>()?, 0x01); // remainder is skipped
+                Ok(())
+            }, 12)
+        })
+    }
+
+    #[test]
+    fn parse_tree() -> Result<()> {
+        // LIST chunk containing two TEST chunks
+        let data = [&[0x4c, 0x49, 0x53, 0x54, 0x00, 0x00, 0x00, 0x18][..], &DATA[12..], &DATA[12..]].concat();
+        let tree = IFFParser::cursor(&data[..]).parse_tree(|header: &IFFHeader| header.typeid == *b"LIST")?;
+        assert_eq!(tree.len(), 1);
+        assert_eq!(tree[0].children.len(), 2);
+        assert_eq!(tree[0].children[1].offset, 28);
+        Ok(())
+    }
+
+    #[test]
+    fn debug_dump() -> Result<()> {
+        // LIST chunk containing two TEST chunks
+        let data = [&[0x4c, 0x49, 0x53, 0x54, 0x00, 0x00, 0x00, 0x18][..], &DATA[12..], &DATA[12..]].concat();
+        let mut out = String::new();
+        IFFParser::cursor(&data[..]).debug_dump(|header: &IFFHeader| header.typeid == *b"LIST", &mut out)?;
+        let lines: Vec<_> = out.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].ends_with(" offset 0x8 size 24") && !lines[0].starts_with(' '));
+        assert!(lines[2].starts_with("  ") && lines[2].ends_with(" offset 0x1c size 4"));
+        Ok(())
+    }
+
+    #[test]
+    fn index() -> Result<()> {
+        // LIST chunk containing two TEST chunks, followed by a TEST chunk
+        let data = [&[0x4c, 0x49, 0x53, 0x54, 0x00, 0x00, 0x00, 0x18][..], &DATA[12..], &DATA[12..], &DATA[12..]].concat();
+        let mut iff = IFFParser::cursor(&data[..]);
+        let index = iff.index(|header: &IFFHeader| header.typeid == *b"LIST")?;
+        assert_eq!(index.iter().map(|(offset, _)| *offset).collect::<Vec<_>>(), [8, 16, 28, 40]);
+        assert!(index[0].1.typeid == *b"LIST" && index[3].1.typeid == *b"TEST");
+        assert_eq!(iff.position()?, 0);
+        assert_eq!(iff.index(|_: &IFFHeader| false)?.len(), 2);
+        Ok(())
+    }
+
+    #[cfg(feature = "crc")]
+    #[test]
+    fn verify_crc32() -> Result<()> {
+        let data: &[u8] = &[b'1', b'2', b'3', b'4', b'5', b'6', b'7', b'8', b'9', 0xcb, 0xf4, 0x39, 0x26];
+        let mut parser = IFFParser::new(std::io::Cursor::new(data));
+        parser.seek(9)?;
+        let crc = parser.read_crc32()?;
+        assert_eq!(crc, 0xcbf43926);
+        parser.verify_crc32(0, 9, crc)?;
+        assert_eq!(parser.position()?, 13);
+        assert!(matches!(parser.verify_crc32(0, 8, crc), Err(Error::ChecksumMismatch { .. })));
+        assert!(matches!(parser.verify_crc32(4, 16, crc), Err(Error::UnexpectedEof)));
+        assert_eq!(parser.position()?, 13);
+        Ok(())
+    }
+
+    #[test]
+    fn read_synchsafe() -> Result<()> {
+        let data: &[u8] = &[0x00, 0x00, 0x02, 0x01, 0x7f, 0x7f, 0x7f, 0x7f, 0x7f, 0x00, 0x80, 0x00, 0x00];
+        let mut parser = IFFParser::new(std::io::Cursor::new(data));
+        assert_eq!(parser.read_synchsafe()?, 0x101);
+        assert_eq!(parser.read_synchsafe35()?, 0x7_ffff_ffff);
+        assert!(matches!(parser.read_synchsafe(), Err(Error::ParseError)));
+        Ok(())
+    }
+
+    #[test]
+    fn read_leb128() -> Result<()> {
+        let data: &[u8] = &[
+            0xe5, 0x8e, 0x26, // 624485
+            0xc0, 0xbb, 0x78, // -123456
+            0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01, // u64::MAX
+            0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x7f, // i64::MIN
+            0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x02, // too large
+        ];
+        let mut parser = IFFParser::new(std::io::Cursor::new(data));
+        assert_eq!(parser.read_uleb128()?, 624485);
+        assert_eq!(parser.read_sleb128()?, -123456);
+        assert_eq!(parser.read_uleb128()?, u64::MAX);
+        assert_eq!(parser.read_sleb128()?, i64::MIN);
+        assert!(matches!(parser.read_uleb128(), Err(Error::SizeOverflow)));
+        Ok(())
+    }
+
+    #[test]
+    fn take_reader_cursor() -> Result<()> {
+        let mut iff = IFFParser::new(std::io::Cursor::new(DATA.to_vec()));
+        iff.skip(4)?;
+        let reader = iff.take_reader()?;
+        assert_eq!(reader.position(), 4);
+        assert_eq!(reader.into_inner(), DATA.to_vec());
+        assert!(matches!(iff.read::<u8>(), Err(Error::UnexpectedEof)));
+        Ok(())
+    }
+
+    #[test]
+    fn take_reader_file() -> Result<()> {
+        let path = std::env::temp_dir().join("chunk-parser-take.iff");
+        std::fs::write(&path, DATA)?;
+        let mut iff = IFFParserFull::open(&path)?;
+        let mut reader = iff.take_reader()?;
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf)?;
+        drop(reader);
+        std::fs::remove_file(&path)?;
+        assert_eq!(buf, DATA);
+        assert!(matches!(iff.read::<u8>(), Err(Error::UnexpectedEof)));
+        Ok(())
+    }
+
+    #[test]
+    fn try_take_reader() -> Result<()> {
+        let mut parser = IFFParserTake { reader: Some(&DATA[..]) };
+        assert!(parser.read_fourcc()? == *b"FORM");
+        let reader = parser.try_take_reader().expect("reader present");
+        assert_eq!(reader.len(), DATA.len() - 4);
+        assert!(parser.try_take_reader().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn chunk_writer() -> Result<()> {
+        let mut iff = IFFWriter { writer: std::io::Cursor::new(Vec::new()), chunks: Vec::new() };
+        let size = iff.chunk(*b"FORM", |writer| {
+            writer.write(b"TEST")?;
+            writer.chunk(*b"TEST", |writer| writer.write_be(0x01020304u32))?;
+            Ok(())
+        })?;
+        assert_eq!(size, 16);
+        assert_eq!(iff.writer.get_ref(), DATA);
+        assert!(matches!(iff.end_chunk(), Err(Error::ParseError)));
+        Ok(())
+    }
+
+    #[test]
+    fn write_round_trip() -> Result<()> {
+        let mut writer = IFFWriter { writer: Vec::new(), chunks: Vec::new() };
+        writer.write(b"TEST")?;
+        writer.write_be(-2i16)?;
+        writer.write_le(0x01020304u32)?;
+        writer.write_int(0x0506u16, Endian::Big)?;
+        writer.write_bytes(&[7, 8])?;
+        let mut parser = IFFParser::new(std::io::Cursor::new(writer.writer));
+        assert!(parser.read_fourcc()? == *b"TEST");
+        assert_eq!(parser.read_be::<i16>()?, -2);
+        assert_eq!(parser.read_le::<u32>()?, 0x01020304);
+        assert_eq!(parser.read_be::<u16>()?, 0x0506);
+        assert_eq!(parser.read_array::<u8, 2>()?, [7, 8]);
+        Ok(())
+    }
+
+    #[test]
+    fn skip_overflow() -> Result<()> {
+        let mut iff = IFFParser::cursor(DATA);
+        assert_eq!(iff.skip(2)?, 2);
+        assert_eq!(iff.skip_to_pos(2)?, 4);
+        assert!(matches!(iff.skip(u64::MAX), Err(Error::SizeOverflow)));
+        assert!(matches!(iff.rewind(5), Err(Error::SeekBeforeStart)));
+        assert_eq!(iff.position()?, 4);
+        assert_eq!(iff.rewind(4)?, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn offset_reader() -> Result<()> {
+        let mut iff = IFFParser::new(OffsetReader::new(std::io::Cursor::new(DATA))?);
+        iff.parse(|parser, header| {
+            parser.skip(4)?;
+            parser.subchunks(|parser, header| {
+                assert_eq!(parser.read_be::<u32>()?, 0x01020304);
+                Ok(header.length as u64)
+            }, header.length as u64 - 4)?;
+            Ok(header.length as u64)
+        })?;
+        assert_eq!(iff.position()?, 24);
+        assert_eq!(iff.rewind(8)?, 16);
+        assert_eq!(iff.read_be::<u32>()?, 4);
+        assert_eq!(iff.reader().position(), 20);
+        Ok(())
+    }
+
+    #[test]
+    fn skip_to() -> Result<()> {
+        let data = [&DATA[12..], &DATA[..]].concat(); // TEST chunk followed by FORM
+        let mut iff = IFFParser::cursor(&data[..]);
+        let header = iff.skip_to::<IFFHeader>(|header| header.typeid == *b"FORM")?.expect("FORM chunk");
+        assert_eq!(header.length, 16);
+        assert_eq!(iff.position()?, 20);
+        iff.skip(header.size())?;
+        assert!(iff.skip_to::<IFFHeader>(|_| true)?.is_none());
+        let mut iff = IFFParser::new(StreamReader::new(&data[..]));
+        assert!(iff.skip_to::<IFFHeader>(|header| header.typeid == *b"DATA")?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn find() -> Result<()> {
+        let data = [&DATA[12..], &DATA[..], &DATA[12..]].concat(); // TEST, FORM, TEST
+        let mut iff = IFFParser::cursor(&data[..]);
+        let header = iff.find::<IFFHeader>(b"FORM")?.expect("FORM chunk");
+        assert_eq!(header.length, 16);
+        assert_eq!(iff.position()?, 20);
+        assert!(iff.find::<IFFHeader>(b"DATA")?.is_none());
+        assert_eq!(iff.find_all::<IFFHeader>(b"TEST")?.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn parse_no_progress() -> Result<()> {
+        let err = EmptyParser::cursor(DATA).parse(|_, _| Ok(0)).unwrap_err();
+        assert!(matches!(err.kind(), Error::ParseError));
+        Ok(())
+    }
+
+    #[test]
+    fn parse_auto_overrun_parent() -> Result<()> {
+        let mut data = DATA.to_vec();
+        data[19] = 0x08; // TEST chunk extends past FORM
+        let mut iff = IFFParser::cursor(&data[..]);
+        let err = iff.parse_auto(|parser, _| {
+            parser.skip(4)?;
+            let pos = parser.position()?;
+            parser.parse_loop_auto(|_, _: &IFFHeader| Ok(()), pos + 12)
+        }).unwrap_err();
+        assert!(matches!(err, Error::Chunk { offset: 12, .. }));
+        assert!(matches!(err.kind(), Error::ParseError));
+        Ok(())
+    }
+
+    #[test]
+    fn subchunks_overrun_parent() -> Result<()> {
+        let mut data = DATA.to_vec();
+        data[19] = 0x08; // TEST chunk extends past FORM
+        let err = IFFParser::cursor(&data[..]).parse(|parser, header| {
+            parser.skip(4)?;
+            parser.subchunks(|_, _| panic!("body of an overrunning chunk"), header.length as u64 - 4)?;
+            Ok(header.length as u64)
+        }).unwrap_err();
+        assert!(matches!(err, Error::Chunk { offset: 12, .. }));
+        assert!(matches!(err.kind(), Error::ParseError));
+        Ok(())
+    }
+
+    #[test]
+    fn subchunks_overrun_parent_unhooked() -> Result<()> {
+        let mut data = [&DATA[..], &[0; 4]].concat(); // trailing bytes after FORM
+        data[19] = 0x08; // TEST chunk extends past FORM
+        let err = BareParser::cursor(&data[..]).parse(|parser, header| {
+            parser.skip(4)?;
+            parser.subchunks(|parser, header| parser.skip(header.length as u64), header.length as u64 - 4)?;
+            Ok(header.length as u64)
+        }).unwrap_err();
+        assert!(matches!(err, Error::Chunk { offset: 12, .. }));
+        assert!(matches!(err.kind(), Error::ParseError));
+        Ok(())
+    }
+
+    #[test]
+    fn read_slice() -> Result<()> {
+        let mut iff = IFFParser::cursor(DATA.to_vec());
+        assert_eq!(iff.read_slice(4)?, b"FORM");
+        iff.seek(20)?;
+        assert_eq!(iff.remaining_slice(), [0x01, 0x02, 0x03, 0x04]);
+        assert!(matches!(iff.read_slice(5), Err(Error::UnexpectedEof)));
+        assert_eq!(iff.read_slice(4)?, [0x01, 0x02, 0x03, 0x04]);
+        assert_eq!(iff.position()?, 24);
+        assert!(iff.remaining_slice().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn read_at() -> Result<()> {
+        let mut iff = IFFParser::cursor(DATA);
+        iff.skip(8)?;
+        assert_eq!(iff.read_at::<u32>(20)?.to_be(), 0x01020304);
+        let mut buf = [0; 4];
+        iff.read_exact_at(12, &mut buf)?;
+        assert_eq!(&buf, b"TEST");
+        assert!(matches!(iff.read_exact_at(22, &mut buf), Err(Error::UnexpectedEof)));
+        assert_eq!(iff.position()?, 8);
+        Ok(())
+    }
+
+    #[test]
+    fn total_size() -> Result<()> {
+        let mut iff = IFFParserFull::new(std::io::Cursor::new(DATA));
+        iff.skip(8)?;
+        assert_eq!(iff.total_size()?, 24);
+        assert_eq!(iff.position()?, 8);
+        iff.parse(|parser, header| {
+            assert_eq!(parser.total_size()?, header.size() + 8);
+            parser.skip(header.size())
+        })?;
+        let mut iff = IFFParserFull::new(std::io::Cursor::new(DATA));
+        assert_eq!(iff.cached_total_size(), None);
+        iff.parse(|parser, header| {
+            assert_eq!(parser.cached_total_size(), Some(24)); // recorded by parse
+            parser.skip(header.size())
+        })
+    }
+
+    #[test]
+    fn parse_checked() -> Result<()> {
+        IFFParser::cursor(DATA).parse_checked(|parser, header: &IFFHeader| parser.skip(header.size()))?;
+        let data = [&DATA[..], &[0, 0]].concat(); // trailing garbage
+        let err = IFFParser::cursor(&data[..]).parse_checked(|parser, header: &IFFHeader| parser.skip(header.size())).unwrap_err();
+        assert!(matches!(err.kind(), Error::SizeMismatch { declared: 16, actual: 18 }));
+        let mut iff = IFFParser::cursor(&DATA[..20]);
+        iff.seek(4)?;
+        let err = iff.parse_checked(|parser, header: &IFFHeader| parser.skip(header.size())).unwrap_err();
+        assert!(matches!(err.kind(), Error::SizeMismatch { declared: 16, actual: 12 }));
+        assert_eq!(iff.position()?, 4); // position restored
+        Ok(())
+    }
+
+    #[test]
+    fn skip_to_end() -> Result<()> {
+        let mut iff = IFFParser::cursor(DATA);
+        iff.skip(12)?;
+        assert_eq!(iff.skip_to_end(20)?, 8);
+        assert_eq!(iff.position()?, 20);
+        assert_eq!(iff.skip_to_end(20)?, 0);
+        assert!(matches!(iff.skip_to_end(16), Err(Error::ChunkOverrun)));
+        Ok(())
+    }
+
+    #[test]
+    fn seek_relative() -> Result<()> {
+        let mut iff = IFFParser::cursor(DATA);
+        assert_eq!(iff.seek_relative(12)?, 12);
+        assert_eq!(iff.seek_relative(-8)?, 4);
+        assert_eq!(iff.seek_relative(0)?, 4);
+        assert!(iff.seek_relative(-5).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn skip_clamped() -> Result<()> {
+        let mut iff = IFFParser::cursor(DATA);
+        assert_eq!(iff.skip_clamped(20)?, 20);
+        assert_eq!(iff.skip_clamped(u64::MAX)?, 4);
+        assert_eq!(iff.position()?, 24);
+        assert_eq!(iff.skip_clamped(1)?, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn parse_flow() -> Result<()> {
+        let data = [&DATA[12..], &DATA[12..]].concat(); // two TEST chunks
+        let mut iff = IFFParser::cursor(&data[..]);
+        iff.parse_flow(|parser, header| {
+            assert!(header.typeid == *b"TEST");
+            assert_eq!(parser.read_be::<u32>()?, 0x01020304);
+            Ok(ControlFlow::Break(()))
+        })?;
+        assert_eq!(iff.position()?, 12);
+        let mut iff = IFFParser::cursor(DATA);
+        iff.parse_flow(|parser, header| {
+            parser.skip(4)?;
+            if parser.subchunks_flow(|_, _| Ok(ControlFlow::Break(())), header.length as u64 - 4)?.is_break() {
+                return Ok(ControlFlow::Break(()))
+            }
+            unreachable!()
+        })?;
+        assert_eq!(iff.position()?, 20);
+        Ok(())
+    }
+
+    #[test]
+    fn parse_with_context() -> Result<()> {
+        IFFParser::cursor(DATA).parse_with_context(|parser, header, context| {
+            assert_eq!(*context, ChunkContext { offset: 0, depth: 0, total_size: 24 });
+            parser.skip(4)?;
+            parser.subchunks_with_context(|parser, header, context| {
+                assert_eq!(*context, ChunkContext { offset: 12, depth: 1, total_size: 24 });
+                parser.skip(header.length as u64)
+            }, header.length as u64 - 4)?;
+            Ok(header.length as u64)
+        })
+    }
+
+    #[test]
+    fn read_packed() -> Result<()> {
+        assert_eq!(std::mem::size_of::<PackedHeader>(), 7);
+        let mut iff = IFFParser::cursor(DATA);
+        iff.skip(3)?;
+        let header = iff.read::<PackedHeader>()?;
+        assert_eq!({ header.tag }, 0x4d);
+        assert_eq!(u32::from_be(header.length), 0x00000010);
+        assert_eq!(u16::from_be(header.flags), 0x5445);
+        let headers = iff.read_vec::<PackedHeader>(2)?;
+        assert_eq!(u32::from_be(headers[1].length), 0x00040102);
+        let mut writer = IFFWriter { writer: Vec::new(), chunks: Vec::new() };
+        writer.write(&header)?;
+        assert_eq!(writer.writer, &DATA[3..10]);
+        Ok(())
+    }
+
+    #[test]
+    fn read_uninit_zeroed() -> Result<()> {
+        use crate::ReaderUninit;
+        let mut reader = &DATA[3..];
+        let header: PackedHeader = unsafe { reader.read_uninit_zeroed()? };
+        assert_eq!(u32::from_be(header.length), 0x00000010);
+        let res: Result<[u32; 5]> = unsafe { reader.read_uninit_zeroed() }; // only 14 bytes remain
+        assert!(matches!(res, Err(Error::UnexpectedEof)));
+        Ok(())
+    }
+
+    #[test]
+    fn read_struct_be() -> Result<()> {
+        let mut iff = IFFParser::cursor(DATA);
+        iff.skip(3)?;
+        let header = iff.read_struct_be::<PackedHeader>()?;
+        assert_eq!({ header.tag }, 0x4d);
+        assert_eq!({ header.length }, 0x00000010);
+        assert_eq!({ header.flags }, 0x5445);
+        iff.seek(16)?;
+        assert_eq!(iff.read_struct_le::<[u16; 2]>()?, [0x0000, 0x0400]);
+        assert_eq!(ByteSwap::swap_bytes(1.0f32).to_bits(), 0x0000803f);
+        Ok(())
+    }
+
+    #[test]
+    fn parse_restores_position() -> Result<()> {
+        let mut iff = IFFParser::cursor(DATA);
+        iff.skip(4)?;
+        assert!(iff.parse(|parser, header| { parser.skip(2)?; Err(parser.unknown_chunk(header)) }).is_err());
+        assert_eq!(iff.position()?, 4);
+        assert!(iff.parse_auto(|parser, header: &IFFHeader| Err(parser.unknown_chunk(header))).is_err());
+        assert_eq!(iff.position()?, 4); // every top level variant restores the position
+        Ok(())
+    }
+
+    #[test]
+    fn parse_from() -> Result<()> {
+        let data = [&[0xff; 6][..], &DATA[12..], &[0xff; 2]].concat(); // TEST chunk embedded in junk
+        let mut iff = IFFParser::cursor(&data[..]);
+        iff.parse_from(|parser, header| {
+            assert!(header.typeid == *b"TEST");
+            parser.skip(header.length as u64)
+        }, 6, 12)?;
+        assert_eq!(iff.position()?, 18);
+        Ok(())
+    }
+
+    #[test]
+    fn parse_counted() -> Result<()> {
+        let mut iff = IFFParserFull::new(std::io::Cursor::new(DATA));
+        iff.parse_counted(|parser, header| {
+            parser.skip(4)?;
+            parser.subchunks_counted(|parser, header| parser.skip(header.size()), header.size() - 4)?;
+            Ok(header.size())
+        })?;
+        assert_eq!(*iff.stats(), ParseStats { chunk_count: 2, max_depth: 1, bytes_parsed: 24, bytes_visited: 36 });
+        Ok(())
+    }
+
+    #[test]
+    fn parse_budget() -> Result<()> {
+        fn parse(parser: &mut IFFParserFull<std::io::Cursor<&[u8; 24]>>, header: &IFFHeader) -> Result<u64> {
+            parser.skip(4)?;
+            parser.subchunks_counted(|parser, header| parser.skip(header.size()), header.size() - 4)?;
+            Ok(header.size())
+        }
+        let mut iff = IFFParserFull { max_chunks: 1, ..IFFParserFull::new(std::io::Cursor::new(DATA)) };
+        let err = iff.parse_counted(parse).unwrap_err();
+        assert!(matches!(err, Error::Chunk { offset: 12, .. }));
+        assert!(matches!(err.kind(), Error::BudgetExceeded));
+        let mut iff = IFFParserFull { max_bytes: 23, ..IFFParserFull::new(std::io::Cursor::new(DATA)) };
+        assert!(matches!(iff.parse_counted(parse).unwrap_err().kind(), Error::BudgetExceeded));
+        let mut iff = IFFParserFull { max_bytes: 35, ..IFFParserFull::new(std::io::Cursor::new(DATA)) };
+        let err = iff.parse_counted(parse).unwrap_err();
+        assert!(matches!(err, Error::Chunk { offset: 12, .. })); // nested bytes count too
+        let mut iff = IFFParserFull { max_chunks: 2, max_bytes: 36, ..IFFParserFull::new(std::io::Cursor::new(DATA)) };
+        iff.parse_counted(parse)
+    }
+
+    #[test]
+    fn parse_budget_plain() -> Result<()> {
+        let mut iff = IFFParserFull { max_bytes: 35, ..IFFParserFull::new(std::io::Cursor::new(DATA)) };
+        let err = iff.parse(|parser, header: &IFFHeader| {
+            parser.skip(4)?;
+            parser.subchunks(|parser, header: &IFFHeader| parser.skip(header.size()), header.size() - 4)?;
+            Ok(header.size())
+        }).unwrap_err();
+        assert!(matches!(err, Error::Chunk { offset: 12, .. })); // budgets hold outside the counted variants
+        assert!(matches!(err.kind(), Error::BudgetExceeded));
+        assert_eq!(iff.stats().chunk_count, 2);
+        Ok(())
+    }
+
+    #[test]
+    fn parse_budget_nested() -> Result<()> {
+        use std::sync::atomic::{AtomicU64, Ordering};
+        static CALLS: AtomicU64 = AtomicU64::new(0);
+        let mut data = vec![0x46, 0x4f, 0x52, 0x4d, 0x00, 0x00, 0x02, 0x04, 0x54, 0x45, 0x53, 0x54]; // "FORM" (516 bytes) "TEST"
+        for _ in 0..64 { data.extend_from_slice(b"NULL\0\0\0\0") } // empty subchunks
+        let mut iff = IFFParserFull { max_bytes: 524 + 8 * 10, ..IFFParserFull::new(std::io::Cursor::new(&data[..])) };
+        let err = iff.parse_counted(|parser, header| {
+            parser.skip(4)?;
+            parser.subchunks_counted(|_, _| { CALLS.fetch_add(1, Ordering::Relaxed); Ok(0) }, header.size() - 4)?;
+            Ok(header.size())
+        }).unwrap_err();
+        assert!(matches!(err, Error::Chunk { offset: 92, .. }));
+        assert!(matches!(err.kind(), Error::BudgetExceeded));
+        assert_eq!(CALLS.load(Ordering::Relaxed), 10); // handler not called for the eleventh subchunk
+        assert_eq!(iff.stats().bytes_parsed, 524); // charged before the handler ran
+        Ok(())
+    }
+
+    #[test]
+    fn validate_header() -> Result<()> {
+        let err = StrictParser::cursor(DATA).parse(|parser, header| {
+            parser.skip(4)?;
+            parser.subchunks(|parser, header| parser.skip(header.length as u64), header.length as u64 - 4)?;
+            Ok(header.length as u64)
+        }).unwrap_err();
+        assert!(matches!(err, Error::Chunk { offset: 12, .. }));
+        assert!(matches!(err.kind(), Error::InvalidFourCC));
+        Ok(())
+    }
+
+    #[test]
+    fn parse_dispatch() -> Result<()> {
+        let data = [&DATA[12..], &DATA[..]].concat(); // TEST chunk followed by FORM
+        let mut dispatcher = ChunkDispatcher::<IFFParser<std::io::Cursor<&[u8]>>, IFFHeader>::new();
+        dispatcher.on(b"TEST", |parser, header| {
+            assert_eq!(parser.read_be::<u32>()?, 0x01020304);
+            Ok(header.length as u64)
+        });
+        let err = IFFParser::cursor(&data[..]).parse_dispatch::<IFFHeader>(&dispatcher).unwrap_err();
+        assert!(matches!(err, Error::Chunk { offset: 12, .. }));
+        assert!(matches!(err.kind(), Error::UnknownChunk { offset: 20, .. }));
+        dispatcher.skip_unknown(true);
+        IFFParser::cursor(&data[..]).parse_dispatch::<IFFHeader>(&dispatcher)
+    }
+
+    #[test]
+    fn to_size() -> Result<()> {
+        assert_eq!(super::to_size(16u32)?, 16);
+        assert_eq!(super::to_size(16u16)?, 16);
+        assert!(matches!(super::to_size(-1i32), Err(Error::SizeOverflow)));
+        assert!(matches!(super::to_size(u128::MAX), Err(Error::SizeOverflow)));
+        assert_eq!(super::to_usize(16)?, 16);
+        if usize::BITS < 64 { assert!(matches!(super::to_usize(u64::MAX), Err(Error::SizeOverflow))) }
+        Ok(())
+    }
+
+    #[test]
+    fn fourcc_ext() -> Result<()> {
+        let fourcc = TypeId::from_bytes(*b"FORM");
+        assert!(fourcc == *b"FORM" && fourcc.matches(b"FORM") && !fourcc.matches(b"LIST"));
+        assert_eq!(&fourcc.to_bytes(), b"FORM");
+        IFFParser::cursor(DATA).parse(|parser, header| {
+            match &header.typeid.to_bytes() {
+                b"FORM" => { parser.skip(4)?; parser.subchunks(|parser, header| parser.skip(header.length as u64), 12)?; },
+                _ => return Err(parser.unknown_chunk(header))
+            }
+            Ok(header.length as u64)
+        })
+    }
+
+    #[test]
+    fn read_endian() -> Result<()> {
+        // tiff style byte order marker followed by the same value twice
+        let data: &[u8] = &[ 0x49, 0x49, 0x2a, 0x00, 0x00, 0x2a ];
+        let mut parser = IFFParserFull::new(std::io::Cursor::new(data));
+        assert_eq!(parser.read_endian::<u16>()?, 0x4949);
+        parser.set_endian(Endian::Little);
+        assert_eq!(parser.read_endian::<u16>()?, 42);
+        parser.set_endian(Endian::Big);
+        assert_eq!(parser.read_endian::<u16>()?, 42);
+        Ok(())
     }
 }
@@ -0,0 +1,157 @@
+//! Standard RIFF parser.
+//!
+//! A ready-made parser for Resource Interchange File Format files such as WAV,
+//! AVI and WEBP. Container chunks `RIFF` and `LIST` are recursed into
+//! automatically, sizes are little endian and chunk bodies are padded to an
+//! even length. Users only supply a handler for the leaf chunks they care about.
+
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+
+use super::io::{Read, Seek, SeekFrom, Cursor};
+
+use super::{
+    Error, Result, FourCC, Endian, StandardHeader, HeaderParser, ChunkHeader, ChunkParser,
+    ChunkContext, ParseStats, ParserReader, ParserRead, ParserSeek, ParserDepth, ParserStats, ParserAlign
+};
+
+//------------------------------------------------------------------------------
+
+/// Signature for RIFF leaf chunk handlers.
+///
+/// The handler receives the form or list type of the enclosing container, or
+/// `None` for top level chunks. Any part of the body left unread is skipped
+/// afterwards.
+pub type RiffFn<R> = fn(parser: &mut RiffParser<R>, form: Option<FourCC>, header: &StandardHeader) -> Result<()>;
+
+/// Check for the RIFF container chunks `RIFF` and `LIST`.
+#[inline] pub fn is_container(fourcc: FourCC) -> bool
+    { fourcc == *b"RIFF" || fourcc == *b"LIST" }
+
+/// Leading `RIFF` chunk header with its form type.
+#[derive(Debug, Clone, Copy)]
+pub struct RiffHeader {
+    pub size: u32, // Size of the form type and all chunks.
+    pub form: FourCC // Form type, e.g. `WAVE` or `AVI `.
+}
+
+/// Standard RIFF parser.
+pub struct RiffParser<R> {
+    reader: R,
+    depth: u8,
+    stats: ParseStats,
+    max_chunks: u64,
+    max_bytes: u64
+}
+
+impl<R> RiffParser<R> {
+    /// Wrap a reader.
+    pub fn new(reader: R) -> RiffParser<R>
+        { RiffParser { reader, depth: 0, stats: ParseStats::default(), max_chunks: u64::MAX, max_bytes: u64::MAX } }
+
+    /// Limit the chunks parsed and bytes covered, see `ParserStats`.
+    pub fn with_budget(self, max_chunks: u64, max_bytes: u64) -> Self { RiffParser { max_chunks, max_bytes, ..self } }
+
+    /// Reclaim the inner reader.
+    pub fn into_inner(self) -> R { self.reader }
+}
+
+impl<T: AsRef<[u8]>> RiffParser<Cursor<T>> {
+    /// Parse an in-memory buffer.
+    pub fn cursor(data: T) -> Self { RiffParser::new(Cursor::new(data)) }
+}
+
+impl RiffParser<Cursor<Vec<u8>>> {
+    /// Parse an owned buffer.
+    pub fn from_vec(data: Vec<u8>) -> Self { RiffParser::cursor(data) }
+}
+
+impl<R> ParserReader<R> for RiffParser<R> { fn reader(&mut self) -> &mut R { &mut self.reader } }
+impl<R: Read> ParserRead<R> for RiffParser<R> {}
+impl<R: Seek> ParserSeek<R> for RiffParser<R> {}
+impl<R> ParserDepth for RiffParser<R> { fn inner_depth(&mut self) -> &mut u8 { &mut self.depth } }
+impl<R> ParserStats for RiffParser<R> {
+    fn stats(&self) -> &ParseStats { &self.stats }
+    fn stats_mut(&mut self) -> &mut ParseStats { &mut self.stats }
+    fn max_chunks(&self) -> u64 { self.max_chunks }
+    fn max_bytes(&self) -> u64 { self.max_bytes }
+}
+impl<R: Seek> ParserAlign<R> for RiffParser<R> { fn alignment(&self) -> u64 { 2 } }
+impl<R: Read + Seek> ChunkParser<R> for RiffParser<R> {
+    fn chunk_alignment(&self) -> u64 { 2 }
+    fn charge(&mut self, context: &ChunkContext, bytes: u64) -> Result<()> { self.charge_chunk(context, bytes) }
+}
+
+impl<R: Read> HeaderParser<StandardHeader> for RiffParser<R> {
+    fn header(&mut self) -> Result<StandardHeader>
+        { self.read_standard_header(Endian::Little) }
+
+    fn as_chunk_header<'a>(&self, header: &'a StandardHeader) -> Option<&'a dyn ChunkHeader> { Some(header) }
+}
+
+impl<R: Read + Seek> RiffParser<R> {
+    /// Read the leading `RIFF` header, leaving the reader at the first chunk.
+    pub fn riff_header(&mut self) -> Result<RiffHeader> {
+        self.seek(0)?;
+        let header = self.header()?;
+        if header.fourcc != *b"RIFF" { return Err(Error::ParseError.in_chunk(Some(header.fourcc), 0)) }
+        Ok( RiffHeader { size: header.size, form: self.read_fourcc()? } )
+    }
+
+    /// Parse the whole reader, calling `f` for every leaf chunk.
+    pub fn parse_riff(&mut self, f: RiffFn<R>) -> Result<()> {
+        let total_size = self.reader.seek(SeekFrom::End(0))?;
+        self.reader.seek(SeekFrom::Start(0))?;
+        super::iff::parse_group(self, f, is_container, None, total_size)
+    }
+}
+
+//------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // WAVE form with an odd sized chunk and an INFO list
+    const DATA: &[u8] = &[
+        0x52, 0x49, 0x46, 0x46, // "RIFF" container chunk
+        0x24, 0x00, 0x00, 0x00, // Chunk size (36 bytes)
+        0x57, 0x41, 0x56, 0x45, // Form type ("WAVE")
+        0x66, 0x6d, 0x74, 0x20, // "fmt " chunk
+        0x03, 0x00, 0x00, 0x00, // Chunk size (3 bytes)
+        0x01, 0x02, 0x03, 0x00, // Test data and pad byte
+        0x4c, 0x49, 0x53, 0x54, // "LIST" container chunk
+        0x0c, 0x00, 0x00, 0x00, // Chunk size (12 bytes)
+        0x49, 0x4e, 0x46, 0x4f, // List type ("INFO")
+        0x49, 0x4e, 0x41, 0x4d, // "INAM" chunk
+        0x00, 0x00, 0x00, 0x00, // Chunk size (0 bytes)
+    ];
+
+    #[test]
+    fn riff_header() -> Result<()> {
+        let mut riff = RiffParser::cursor(DATA);
+        let header = riff.riff_header()?;
+        assert_eq!(header.size, 36);
+        assert!(header.form == *b"WAVE");
+        assert_eq!(riff.position()?, 12);
+        let err = RiffParser::cursor(&DATA[12..]).riff_header().unwrap_err();
+        assert!(matches!(err.kind(), Error::ParseError));
+        Ok(())
+    }
+
+    #[test]
+    fn parse_riff() -> Result<()> {
+        RiffParser::cursor(DATA).parse_riff(|parser, form, header| {
+            let form = form.expect("leaf inside a container");
+            if header.fourcc == *b"fmt " {
+                assert!(form == *b"WAVE");
+                assert_eq!(parser.depth(), 1);
+                assert_eq!(parser.read_le::<u16>()?, 0x0201); // remainder is skipped
+            } else {
+                assert!(header.fourcc == *b"INAM" && form == *b"INFO");
+                assert_eq!(parser.depth(), 2);
+            }
+            Ok(())
+        })
+    }
+}